@@ -0,0 +1,84 @@
+/// Expands `{a,b,c}` groups in the `pattern` like shells do: `*.{png,jpg}` becomes `*.png` and
+/// `*.jpg`. Groups can be nested and can contain empty alternatives, e.g. `file{,-old}`.
+/// Malformed groups, i.e. an unmatched `{` or braces without a comma inside, are left as is.
+/// The alternatives are returned in the order they are written.
+pub(crate) fn expand_braces(pattern: &str) -> Vec<String> {
+    for (open_position, _) in pattern.match_indices('{') {
+        let Some((close_position, comma_positions)) = find_group(&pattern[open_position..]) else {
+            continue;
+        };
+        if comma_positions.is_empty() {
+            continue;
+        }
+        let prefix = &pattern[..open_position];
+        let group = &pattern[open_position..open_position + close_position];
+        let suffixes = expand_braces(&pattern[open_position + close_position + 1..]);
+        let mut alternative_begin = 1;
+        let mut result = vec![];
+        for alternative_end in comma_positions.into_iter().chain([group.len()]) {
+            for alternative in expand_braces(&group[alternative_begin..alternative_end]) {
+                for suffix in &suffixes {
+                    result.push(format!("{prefix}{alternative}{suffix}"));
+                }
+            }
+            alternative_begin = alternative_end + 1;
+        }
+        return result;
+    }
+    vec![pattern.to_string()]
+}
+
+/// Finds the `}` matching the `{` which `string` starts with. Returns its position and
+/// positions of commas separating top-level alternatives.
+fn find_group(string: &str) -> Option<(usize, Vec<usize>)> {
+    let mut depth = 0usize;
+    let mut comma_positions = vec![];
+    for (position, symbol) in string.char_indices() {
+        match symbol {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some((position, comma_positions));
+                }
+            }
+            ',' if depth == 1 => comma_positions.push(position),
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod test_expand_braces {
+    use super::expand_braces;
+
+    fn check(pattern: &str, expected: &[&str]) {
+        assert_eq!(expand_braces(pattern), expected, "{pattern}");
+    }
+
+    #[test]
+    fn it_works() {
+        check("*.{png,jpg}", &["*.png", "*.jpg"]);
+        check("file{1,2}.txt", &["file1.txt", "file2.txt"]);
+        check("{a,b}-{c,d}", &["a-c", "a-d", "b-c", "b-d"]);
+        check("file{,-old}", &["file", "file-old"]);
+        check("no-braces", &["no-braces"]);
+    }
+
+    #[test]
+    fn nested() {
+        check("{a,b{c,d}}.txt", &["a.txt", "bc.txt", "bd.txt"]);
+        check("{{a,b}}", &["{a}", "{b}"]);
+    }
+
+    #[test]
+    fn malformed() {
+        check("{}", &["{}"]);
+        check("{single}", &["{single}"]);
+        check("{a,b", &["{a,b"]);
+        check("a,b}", &["a,b}"]);
+        check("{{a,b}", &["{a", "{b"]);
+        check("{x}{a,b}", &["{x}a", "{x}b"]);
+    }
+}
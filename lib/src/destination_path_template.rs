@@ -1,4 +1,12 @@
-use std::{path::PathBuf, str::pattern::Pattern};
+use std::{borrow::Cow, fmt::Display, path::PathBuf};
+
+use chrono::{Datelike, Local, NaiveDateTime, Timelike};
+
+use crate::{
+    marker::Marker,
+    source_path_pattern::expand_tilde,
+    variable_expansion::{expand_variables, UnknownVariableError},
+};
 
 /// Destination path template. Can contain special markers such as `#1`, `#2`, etc.
 /// These markers are to be replaced by another symbols, for instance by fragments of source
@@ -7,8 +15,12 @@ use std::{path::PathBuf, str::pattern::Pattern};
 pub struct DestinationPathTemplate<'a> {
     /// Directory containing a destination file
     pub directory: PathBuf,
-    markers: Vec<u8>,
-    literal_blocks: Vec<&'a str>,
+    markers: Vec<Marker>,
+    literal_blocks: Vec<Cow<'a, str>>,
+    /// The greatest index referenced by `#N` in the literal text of the pattern, see
+    /// [`max_referenced_index`][DestinationPathTemplate::max_referenced_index]. It's computed
+    /// before variables are expanded, so their values don't reference anything.
+    max_literal_index: usize,
 }
 
 impl<'a> DestinationPathTemplate<'a> {
@@ -16,6 +28,27 @@ impl<'a> DestinationPathTemplate<'a> {
     /// `max_marker_index` is typically a number of wildcards in a corresponding `SourcePathPattern`.
     /// A marker with the greatest possible (i.e. not greater than `max_marker_index`) index will be used in case of ambiguity.
     /// For example, if `max_marker_index` is `10`, then `"#100"` will be treated as marker `#10` and literal `"0"`.
+    ///
+    /// Marker `#0` refers to the whole source filename and is valid for any `max_marker_index`.
+    /// However, `#0` followed by a digit isn't a marker, e.g. `#01` is a literal text.
+    /// Negative markers count fragments from the end: `#-1` refers to the last fragment,
+    /// `#-2` to the one before it, and so on. The same greedy rule applies to them.
+    /// A pattern ending with `/` means moving into the directory keeping the source filename,
+    /// i.e. `dir/` is the same as `dir/#0`.
+    ///
+    /// A marker can be followed by a width specifier: `#1:03` pads the fragment with zeros
+    /// to width `3` if the fragment is numeric (i.e. consists of ASCII digits only) and leaves it
+    /// as is otherwise, `#1:3` pads any fragment with spaces on the left. Case modifiers `#1:lower`,
+    /// `#1:upper` and `#1:title` change the case of the fragment. Modifiers can be chained,
+    /// e.g. `#1:upper:5`, and are applied from left to right. A slice `#1[BEGIN..END]` takes
+    /// characters of the fragment from `BEGIN` to `END` exclusively, both bounds are optional
+    /// and are clamped to the fragment length. An offset `#1+100` or `#1:add=-100` adds a number
+    /// to an integer fragment keeping the width of a zero-padded one, other fragments are left
+    /// as is. An invalid modifier, e.g. `:0`, `:bogus` or `[1.]`,
+    /// is treated as literal text.
+    /// A leading `~` in the directory is expanded to the home directory, `~user` is not supported.
+    /// Date and time tokens such as `%Y` are replaced on substitution, see
+    /// [`substitute_with_time`][DestinationPathTemplate::substitute_with_time].
     /// See [`substitute`][DestinationPathPattern::substitute] method for more exmaples.
     ///
     /// # Exmaples
@@ -28,51 +61,119 @@ impl<'a> DestinationPathTemplate<'a> {
     /// );
     /// ```
     pub fn compile(path_pattern: &'a str, max_marker_index: u8) -> Self {
-        let (directory, mut filename_remainder) =
+        let (directory, filename) =
             path_pattern.split_at(path_pattern.rfind('/').map_or(0, |index| index + 1));
         let mut markers = vec![];
         let mut literal_blocks = vec![];
-        let mut current_offset_in_filename = 0;
-        'find_marker: while let Some(hashtag_position) =
-            &filename_remainder[current_offset_in_filename..].find('#')
+        let mut block_begin = 0;
+        let mut search_begin = 0;
+        while let Some(hashtag_position) = filename[search_begin..]
+            .find('#')
+            .map(|position| search_begin + position)
         {
-            for marker_index in (1..=max_marker_index).rev() {
-                if marker_index.to_string().is_prefix_of(
-                    &filename_remainder[current_offset_in_filename + hashtag_position + 1..],
-                ) {
-                    literal_blocks
-                        .push(&filename_remainder[..current_offset_in_filename + hashtag_position]);
-                    markers.push(marker_index);
-                    filename_remainder = &filename_remainder[current_offset_in_filename
-                        + hashtag_position
-                        + marker_index.to_string().len()
-                        + 1..];
-                    current_offset_in_filename = 0;
-                    continue 'find_marker;
+            match Marker::parse(&filename[hashtag_position + 1..], max_marker_index) {
+                Some((marker, marker_length)) => {
+                    literal_blocks.push(filename[block_begin..hashtag_position].into());
+                    markers.push(marker);
+                    block_begin = hashtag_position + marker_length + 1;
+                    search_begin = block_begin;
                 }
+                None => search_begin = hashtag_position + 1,
             }
-            current_offset_in_filename = hashtag_position + 1;
         }
-        literal_blocks.push(filename_remainder);
+        literal_blocks.push(filename[block_begin..].into());
+        if path_pattern.ends_with('/') {
+            // Moving into the directory keeping the source filename, like `#0`
+            markers.push(0.into());
+            literal_blocks.push("".into());
+        }
         Self {
-            directory: directory.into(),
+            directory: expand_tilde(directory, std::env::var_os("HOME")),
             markers,
+            max_literal_index: max_literal_index(&literal_blocks),
             literal_blocks,
         }
     }
 
+    /// Like [`compile`][DestinationPathTemplate::compile], but also expands variables in
+    /// the pattern: `$NAME` and `${NAME}` are replaced by values returned by `lookup`, e.g.
+    /// `std::env::var(name).ok()`, and `$$` is replaced by a single `$`. A variable name consists
+    /// of ASCII letters, digits and underscores and doesn't start with a digit, any other `$`
+    /// is left as is. Fails if some variable isn't set, i.e. `lookup` returns `None`.
+    ///
+    /// Variables are expanded after markers are parsed, so values are always literal text:
+    /// neither markers nor date and time tokens in them are replaced.
+    ///
+    /// # Examples
+    /// ```
+    /// use mmv_lib::DestinationPathTemplate;
+    /// let lookup = |name: &str| (name == "ARCHIVE_DIR").then(|| "/srv/archive".to_string());
+    /// let template =
+    ///     DestinationPathTemplate::compile_with_variables("$ARCHIVE_DIR/#1.bak", 1, lookup)
+    ///         .unwrap();
+    /// assert_eq!(
+    ///     template.substitute("notes.txt", &["notes"]),
+    ///     std::path::PathBuf::from("/srv/archive/notes.bak")
+    /// );
+    /// assert!(DestinationPathTemplate::compile_with_variables("$MISSING/#1", 1, lookup).is_err());
+    /// ```
+    pub fn compile_with_variables(
+        path_pattern: &'a str,
+        max_marker_index: u8,
+        lookup: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self, UnknownVariableError> {
+        let mut template = Self::compile(path_pattern, max_marker_index);
+        let directory = &path_pattern[..path_pattern.rfind('/').map_or(0, |index| index + 1)];
+        template.directory = expand_tilde(
+            &expand_variables(directory, &lookup)?,
+            std::env::var_os("HOME"),
+        );
+        // `%` is escaped, so that values aren't affected by `expand_time_tokens`
+        let escaped_lookup = |name: &str| lookup(name).map(|value| value.replace('%', "%%"));
+        for block in &mut template.literal_blocks {
+            if let Cow::Owned(expanded) = expand_variables(block, &escaped_lookup)? {
+                *block = Cow::Owned(expanded);
+            }
+        }
+        Ok(template)
+    }
+
+    /// Returns the greatest marker index referenced by the template, including indices
+    /// which are too big to be markers and so are treated as literal text. The greedy rule
+    /// still applies: if `max_marker_index` is `10`, `"#100"` references index `10`.
+    /// `#` followed by digits starting with `0` references the index they form, e.g. `#01` references `1`.
+    /// A negative marker `#-N` references index `N`, since it needs at least `N` fragments.
+    ///
+    /// # Examples
+    /// ```
+    /// use mmv_lib::DestinationPathTemplate;
+    /// let template = DestinationPathTemplate::compile("#1-#3", 2);
+    /// assert_eq!(template.max_referenced_index(), 3);
+    /// assert_eq!(DestinationPathTemplate::compile("#100", 10).max_referenced_index(), 10);
+    /// ```
+    pub fn max_referenced_index(&self) -> usize {
+        self.markers
+            .iter()
+            .map(|marker| marker.index.unsigned_abs() as usize)
+            .max()
+            .unwrap_or(0)
+            .max(self.max_literal_index)
+    }
+
     /// Subtitutes `fragments_values` instead of markers: `#1` is replaced by
-    /// `fragments_values[0]`, etc.
+    /// `fragments_values[0]`, etc. `#0` is replaced by `source_filename`, i.e. the whole filename
+    /// of the file the fragments are matched from.
     ///
     /// # Panics
-    /// Panics if `fragments_values` doesn't contain enough fragments to substitute.
+    /// Panics if `fragments_values` doesn't contain enough fragments to substitute,
+    /// see [`try_substitute`][DestinationPathTemplate::try_substitute] for a non-panicking version.
     ///
     /// # Examples
     /// ```
     /// use mmv_lib::DestinationPathTemplate;
     /// let rust_version_pattern = DestinationPathTemplate::compile("rust/version-#1.#2", 2);
     /// assert_eq!(
-    ///     rust_version_pattern.substitute(&["1", "75"]),
+    ///     rust_version_pattern.substitute("rust-1.75", &["1", "75"]),
     ///     std::path::PathBuf::from("rust/version-1.75")
     /// );
     ///
@@ -81,25 +182,234 @@ impl<'a> DestinationPathTemplate<'a> {
     /// assert_eq!(
     ///     // no matter how many fragment are passed,
     ///     // markers with indices greater than 1 won't work
-    ///     unparsed_marker.substitute(&["hello", "world"]),
+    ///     unparsed_marker.substitute("hello.world", &["hello", "world"]),
     ///     std::path::PathBuf::from("filehello.#2")
     /// );
+    ///
+    /// let padded_pattern = DestinationPathTemplate::compile("img_#1:03.#2", 2);
+    /// assert_eq!(
+    ///     padded_pattern.substitute("7.png", &["7", "png"]),
+    ///     std::path::PathBuf::from("img_007.png")
+    /// );
+    ///
+    /// let backup_pattern = DestinationPathTemplate::compile("backup/#0.bak", 0);
+    /// assert_eq!(
+    ///     backup_pattern.substitute("original", &[]),
+    ///     std::path::PathBuf::from("backup/original.bak")
+    /// );
     /// ```
-    pub fn substitute(&self, fragments_values: &[&str]) -> PathBuf {
-        let mut result_filename = self.literal_blocks[0].to_string();
-        for (marker_index, block) in self.markers.iter().zip(self.literal_blocks.iter().skip(1)) {
-            result_filename += fragments_values[*marker_index as usize - 1];
-            result_filename += block;
+    pub fn substitute(&self, source_filename: &str, fragments_values: &[&str]) -> PathBuf {
+        self.try_substitute(source_filename, fragments_values)
+            .unwrap_or_else(|error| panic!("{error}"))
+    }
+
+    /// Like [`substitute`][DestinationPathTemplate::substitute], but returns an error instead
+    /// of panicking if `fragments_values` doesn't contain enough fragments to substitute.
+    ///
+    /// Date and time tokens are replaced according to the current time taken on each call,
+    /// so files substituted one by one may get different `%M` or `%S`. To substitute a batch
+    /// consistently, use [`substitute_with_time`][DestinationPathTemplate::substitute_with_time]
+    /// with a single time shared by all the files, or fix the time in advance with
+    /// [`at_current_time`][DestinationPathTemplate::at_current_time].
+    ///
+    /// # Examples
+    /// ```
+    /// use mmv_lib::{DestinationPathTemplate, SubstituteError};
+    /// let pattern = DestinationPathTemplate::compile("#1-#2", 2);
+    /// assert_eq!(
+    ///     pattern.try_substitute("a-b", &["a", "b"]),
+    ///     Ok(std::path::PathBuf::from("a-b"))
+    /// );
+    /// assert_eq!(
+    ///     pattern.try_substitute("a", &["a"]),
+    ///     Err(SubstituteError { marker_index: 2, fragments_number: 1 })
+    /// );
+    /// ```
+    pub fn try_substitute(
+        &self,
+        source_filename: &str,
+        fragments_values: &[&str],
+    ) -> Result<PathBuf, SubstituteError> {
+        if let Some(marker) = self
+            .markers
+            .iter()
+            .find(|marker| !marker.fragment_exists(fragments_values.len()))
+        {
+            return Err(SubstituteError {
+                marker_index: marker.index,
+                fragments_number: fragments_values.len(),
+            });
+        }
+        Ok(self.substitute_with_time(
+            source_filename,
+            fragments_values,
+            &Local::now().naive_local(),
+        ))
+    }
+
+    /// Like [`substitute`][DestinationPathTemplate::substitute], but date and time tokens
+    /// in the filename are replaced according to `time` instead of the current local time:
+    /// `%Y` is replaced by the year, `%m` by the month, `%d` by the day, `%H` by the hour,
+    /// `%M` by the minute and `%S` by the second, all of them except the year are two digits long.
+    /// `%%` is replaced by a single `%`, any other `%` is left as is. Tokens are recognized in
+    /// the literal text only, so substituted fragments are never affected.
+    ///
+    /// Unlike [`substitute`][DestinationPathTemplate::substitute], `fragments_values` can be
+    /// any strings, e.g. owned ones, so they don't have to be borrowed into a new slice.
+    ///
+    /// # Panics
+    /// Panics if `fragments_values` doesn't contain enough fragments to substitute,
+    /// like [`substitute`][DestinationPathTemplate::substitute].
+    ///
+    /// # Examples
+    /// ```
+    /// use chrono::NaiveDate;
+    /// use mmv_lib::DestinationPathTemplate;
+    /// let time = NaiveDate::from_ymd_opt(2024, 3, 7)
+    ///     .unwrap()
+    ///     .and_hms_opt(9, 5, 0)
+    ///     .unwrap();
+    /// let archive_pattern = DestinationPathTemplate::compile("archive/#1-%Y%m%d.log", 1);
+    /// assert_eq!(
+    ///     archive_pattern.substitute_with_time("server.log", &["server"], &time),
+    ///     std::path::PathBuf::from("archive/server-20240307.log")
+    /// );
+    /// ```
+    pub fn substitute_with_time(
+        &self,
+        source_filename: &str,
+        fragments_values: &[impl AsRef<str>],
+        time: &NaiveDateTime,
+    ) -> PathBuf {
+        let mut result_filename = String::new();
+        expand_time_tokens(&self.literal_blocks[0], time, &mut result_filename);
+        for (marker, block) in self.markers.iter().zip(self.literal_blocks.iter().skip(1)) {
+            marker.substitute(source_filename, fragments_values, &mut result_filename);
+            expand_time_tokens(block, time, &mut result_filename);
         }
         self.directory.join(result_filename)
     }
+
+    /// Returns the template with date and time tokens replaced according to the current local
+    /// time, see [`substitute_with_time`][DestinationPathTemplate::substitute_with_time].
+    /// Substituting the result gives the same paths at any time, e.g. when the same moves are
+    /// planned twice.
+    ///
+    /// # Examples
+    /// ```
+    /// use chrono::NaiveDate;
+    /// use mmv_lib::DestinationPathTemplate;
+    /// let template = DestinationPathTemplate::compile("#1-%S%%", 1).at_current_time();
+    /// let midnight = NaiveDate::from_ymd_opt(2024, 3, 7)
+    ///     .unwrap()
+    ///     .and_hms_opt(0, 0, 0)
+    ///     .unwrap();
+    /// assert!(template
+    ///     .substitute_with_time("a", &["a"], &midnight)
+    ///     .to_str()
+    ///     .unwrap()
+    ///     .ends_with('%'));
+    /// ```
+    pub fn at_current_time(&self) -> Self {
+        let now = Local::now().naive_local();
+        let literal_blocks = self
+            .literal_blocks
+            .iter()
+            .map(|block| {
+                let mut expanded_block = String::new();
+                expand_time_tokens(block, &now, &mut expanded_block);
+                // `%` is escaped, so that the block is kept as is by later substitutions
+                Cow::Owned(expanded_block.replace('%', "%%"))
+            })
+            .collect();
+        Self {
+            literal_blocks,
+            ..self.clone()
+        }
+    }
+}
+
+/// Error returned by [`DestinationPathTemplate::try_substitute`] if a marker refers to
+/// a fragment which isn't given
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SubstituteError {
+    /// Index of the first marker in the template referring to a missing fragment
+    pub marker_index: i16,
+    /// Number of the given fragments
+    pub fragments_number: usize,
+}
+
+impl Display for SubstituteError {
+    fn fmt(&self, format: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            format,
+            "Marker #{} refers to a missing fragment: only {} fragments are given",
+            self.marker_index, self.fragments_number
+        )
+    }
+}
+
+impl std::error::Error for SubstituteError {}
+
+/// Returns the greatest index referenced by `#N` or `#-N` in `literal_blocks`, see
+/// [`DestinationPathTemplate::max_referenced_index`]
+fn max_literal_index(literal_blocks: &[Cow<str>]) -> usize {
+    literal_blocks
+        .iter()
+        .flat_map(|block| {
+            block
+                .match_indices('#')
+                .filter_map(|(hashtag_position, _)| {
+                    let digits = &block[hashtag_position + 1..];
+                    let digits = digits.strip_prefix('-').unwrap_or(digits);
+                    let digits = &digits[..digits
+                        .find(|symbol: char| !symbol.is_ascii_digit())
+                        .unwrap_or(digits.len())];
+                    (!digits.is_empty()).then(|| digits.parse().unwrap_or(usize::MAX))
+                })
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+/// Appends `text` to `output` replacing date and time tokens,
+/// see [`DestinationPathTemplate::substitute_with_time`]
+fn expand_time_tokens(text: &str, time: &NaiveDateTime, output: &mut String) {
+    let mut rest = text;
+    while let Some(percent_position) = rest.find('%') {
+        output.push_str(&rest[..percent_position]);
+        rest = &rest[percent_position + 1..];
+        let expansion = match rest.chars().next() {
+            Some('Y') => format!("{:04}", time.year()),
+            Some('m') => format!("{:02}", time.month()),
+            Some('d') => format!("{:02}", time.day()),
+            Some('H') => format!("{:02}", time.hour()),
+            Some('M') => format!("{:02}", time.minute()),
+            Some('S') => format!("{:02}", time.second()),
+            Some('%') => "%".to_string(),
+            _ => {
+                output.push('%');
+                continue;
+            }
+        };
+        output.push_str(&expansion);
+        rest = &rest[1..];
+    }
+    output.push_str(rest);
 }
 
 #[cfg(test)]
 mod test_destination_path_pattern {
-    use std::path::PathBuf;
+    use std::{borrow::Cow, path::PathBuf};
 
-    use super::DestinationPathTemplate;
+    use chrono::NaiveDate;
+
+    use super::{DestinationPathTemplate, SubstituteError, UnknownVariableError};
+    use crate::marker::{Marker, Modifier};
+
+    fn blocks(blocks: &[&'static str]) -> Vec<Cow<'static, str>> {
+        blocks.iter().map(|&block| block.into()).collect()
+    }
 
     #[test]
     fn compile() {
@@ -107,8 +417,9 @@ mod test_destination_path_pattern {
             DestinationPathTemplate::compile("file_#1_name.#2", 2),
             DestinationPathTemplate {
                 directory: "".into(),
-                markers: vec![1, 2],
-                literal_blocks: vec!["file_", "_name.", ""]
+                markers: vec![1.into(), 2.into()],
+                literal_blocks: blocks(&["file_", "_name.", ""]),
+                max_literal_index: 0
             }
         );
 
@@ -116,8 +427,9 @@ mod test_destination_path_pattern {
             DestinationPathTemplate::compile("#1#2#1#1#2", 2),
             DestinationPathTemplate {
                 directory: "".into(),
-                markers: vec![1, 2, 1, 1, 2],
-                literal_blocks: vec![""; 6]
+                markers: vec![1.into(), 2.into(), 1.into(), 1.into(), 2.into()],
+                literal_blocks: blocks(&[""; 6]),
+                max_literal_index: 0
             },
         );
 
@@ -125,8 +437,9 @@ mod test_destination_path_pattern {
             DestinationPathTemplate::compile("#1#12#123#1#123", 12),
             DestinationPathTemplate {
                 directory: "".into(),
-                markers: vec![1, 12, 12, 1, 12],
-                literal_blocks: vec!["", "", "", "3", "", "3"]
+                markers: vec![1.into(), 12.into(), 12.into(), 1.into(), 12.into()],
+                literal_blocks: blocks(&["", "", "", "3", "", "3"]),
+                max_literal_index: 0
             }
         );
 
@@ -134,8 +447,9 @@ mod test_destination_path_pattern {
             DestinationPathTemplate::compile("path/to/file_##1.#2", 5),
             DestinationPathTemplate {
                 directory: "path/to/".into(),
-                markers: vec![1, 2],
-                literal_blocks: vec!["file_#", ".", ""]
+                markers: vec![1.into(), 2.into()],
+                literal_blocks: blocks(&["file_#", ".", ""]),
+                max_literal_index: 0
             }
         );
 
@@ -143,8 +457,9 @@ mod test_destination_path_pattern {
             DestinationPathTemplate::compile("path#1/#1#2.png", 3),
             DestinationPathTemplate {
                 directory: PathBuf::from("path#1/"),
-                markers: vec![1, 2],
-                literal_blocks: vec!["", "", ".png"]
+                markers: vec![1.into(), 2.into()],
+                literal_blocks: blocks(&["", "", ".png"]),
+                max_literal_index: 0
             }
         );
 
@@ -152,8 +467,9 @@ mod test_destination_path_pattern {
             DestinationPathTemplate::compile("/absolute/path/#20.#2", 20),
             DestinationPathTemplate {
                 directory: PathBuf::from("/absolute/path/"),
-                markers: vec![20, 2],
-                literal_blocks: vec!["", ".", ""]
+                markers: vec![20.into(), 2.into()],
+                literal_blocks: blocks(&["", ".", ""]),
+                max_literal_index: 0
             }
         );
 
@@ -161,27 +477,332 @@ mod test_destination_path_pattern {
             DestinationPathTemplate::compile("/file_in_root#1.png", 1),
             DestinationPathTemplate {
                 directory: PathBuf::from("/"),
-                markers: vec![1],
-                literal_blocks: vec!["file_in_root", ".png"]
+                markers: vec![1.into()],
+                literal_blocks: blocks(&["file_in_root", ".png"]),
+                max_literal_index: 0
+            }
+        );
+    }
+
+    #[test]
+    fn compile_width_specifiers() {
+        assert_eq!(
+            DestinationPathTemplate::compile("img_#1:03.#2:x", 2),
+            DestinationPathTemplate {
+                directory: "".into(),
+                markers: vec![
+                    Marker {
+                        index: 1,
+                        modifiers: vec![Modifier::Pad {
+                            width: 3,
+                            with_zeros: true
+                        }]
+                    },
+                    2.into()
+                ],
+                literal_blocks: blocks(&["img_", ".", ":x"]),
+                max_literal_index: 0
+            }
+        );
+
+        assert_eq!(
+            DestinationPathTemplate::compile("a#b#c#1:0", 1),
+            DestinationPathTemplate {
+                directory: "".into(),
+                markers: vec![1.into()],
+                literal_blocks: blocks(&["a#b#c", ":0"]),
+                max_literal_index: 0
             }
         );
     }
 
+    #[test]
+    fn compile_with_variables() {
+        let lookup = |name: &str| match name {
+            "ARCHIVE_DIR" => Some("/srv/archive".to_string()),
+            "SUFFIX" => Some("#2-old".to_string()),
+            _ => None,
+        };
+        let template =
+            DestinationPathTemplate::compile_with_variables("$ARCHIVE_DIR/#1.${SUFFIX}", 2, lookup)
+                .unwrap();
+        assert_eq!(template.directory, PathBuf::from("/srv/archive/"));
+        assert_eq!(
+            template.substitute("notes.txt", &["notes", "txt"]),
+            PathBuf::from("/srv/archive/notes.#2-old")
+        );
+        assert_eq!(
+            DestinationPathTemplate::compile_with_variables("$$#1-$SUFFIX/", 1, lookup)
+                .unwrap()
+                .substitute("notes", &["notes"]),
+            PathBuf::from("$#1-#2-old/notes")
+        );
+        assert_eq!(
+            DestinationPathTemplate::compile_with_variables("dir/#1$MISSING", 1, lookup),
+            Err(UnknownVariableError {
+                name: "MISSING".to_string()
+            })
+        );
+        assert_eq!(
+            DestinationPathTemplate::compile_with_variables("$$#1", 1, lookup).unwrap(),
+            DestinationPathTemplate::compile("$#1", 1)
+        );
+    }
+
+    #[test]
+    fn variable_values_are_literal() {
+        let time = NaiveDate::from_ymd_opt(2026, 1, 2)
+            .unwrap()
+            .and_hms_opt(3, 4, 5)
+            .unwrap();
+        let lookup = |name: &str| (name == "VALUE").then(|| "50%%_%Y#7".to_string());
+        let template =
+            DestinationPathTemplate::compile_with_variables("#1-$VALUE-%Y", 1, lookup).unwrap();
+        assert_eq!(
+            template.substitute_with_time("a", &["a"], &time),
+            PathBuf::from("a-50%%_%Y#7-2026")
+        );
+        assert_eq!(template.max_referenced_index(), 1);
+        assert_eq!(
+            DestinationPathTemplate::compile_with_variables("#3$VALUE", 1, lookup)
+                .unwrap()
+                .max_referenced_index(),
+            3
+        );
+    }
+
+    #[test]
+    fn substitute_time_tokens() {
+        let time = NaiveDate::from_ymd_opt(987, 1, 2)
+            .unwrap()
+            .and_hms_opt(3, 4, 5)
+            .unwrap();
+        let check = |pattern: &str, fragments: &[&str], expected: &str| {
+            assert_eq!(
+                DestinationPathTemplate::compile(pattern, fragments.len() as u8)
+                    .substitute_with_time("source", fragments, &time),
+                PathBuf::from(expected),
+                "{pattern}"
+            );
+        };
+        check("#1-%Y%m%d.log", &["app"], "app-09870102.log");
+        check("%H:%M:%S #1", &["%Y"], "03:04:05 %Y");
+        check("100%%-%Y", &[], "100%-0987");
+        check("%y %Q %", &[], "%y %Q %");
+        check("%%Y", &[], "%Y");
+        check("dir%Y/%Y", &[], "dir%Y/0987");
+    }
+
+    #[test]
+    fn at_current_time() {
+        let time = NaiveDate::from_ymd_opt(987, 1, 2)
+            .unwrap()
+            .and_hms_opt(3, 4, 5)
+            .unwrap();
+        let template = DestinationPathTemplate::compile("#1-%Y-100%%", 1);
+        let fixed_template = template.at_current_time();
+        let destination = fixed_template
+            .substitute_with_time("a", &["a"], &time)
+            .to_str()
+            .unwrap()
+            .to_string();
+        assert!(destination.starts_with("a-"), "{destination}");
+        assert!(destination.ends_with("-100%"), "{destination}");
+        assert_ne!(destination, "a-0987-100%");
+        assert_eq!(fixed_template.max_referenced_index(), 1);
+    }
+
+    #[test]
+    fn substitute_width_specifiers() {
+        let pattern = DestinationPathTemplate::compile("img_#1:03.#2", 2);
+        assert_eq!(
+            pattern.substitute("7.png", &["7", "png"]),
+            PathBuf::from("img_007.png")
+        );
+        assert_eq!(
+            pattern.substitute("cover.png", &["cover", "png"]),
+            PathBuf::from("img_cover.png")
+        );
+        assert_eq!(
+            DestinationPathTemplate::compile("#1:6", 1).substitute("cover", &["cover"]),
+            PathBuf::from(" cover")
+        );
+    }
+
+    #[test]
+    fn substitute_negative_markers() {
+        let template = DestinationPathTemplate::compile("#-1-#-3.#-4", 3);
+        assert_eq!(
+            template.substitute("abc", &["a", "b", "c"]),
+            PathBuf::from("c-a.#-4")
+        );
+        assert_eq!(template.max_referenced_index(), 4);
+        assert_eq!(
+            DestinationPathTemplate::compile("#-2", 2).try_substitute("a", &["a"]),
+            Err(SubstituteError {
+                marker_index: -2,
+                fragments_number: 1
+            })
+        );
+    }
+
+    #[test]
+    fn substitute_offset() {
+        let pattern = DestinationPathTemplate::compile("img-#1+5.#2", 2);
+        assert_eq!(
+            pattern.substitute("img-10.png", &["10", "png"]),
+            PathBuf::from("img-15.png")
+        );
+        assert_eq!(
+            pattern.substitute("img-abc.png", &["abc", "png"]),
+            PathBuf::from("img-abc.png")
+        );
+    }
+
+    #[test]
+    fn substitute_case_modifiers() {
+        let pattern = DestinationPathTemplate::compile("photos/#1.#2:lower", 2);
+        assert_eq!(
+            pattern.substitute("photo.JPG", &["photo", "JPG"]),
+            PathBuf::from("photos/photo.jpg")
+        );
+        assert_eq!(
+            DestinationPathTemplate::compile("#1:upper", 1).substitute("rs", &["rs"]),
+            PathBuf::from("RS")
+        );
+        assert_eq!(
+            DestinationPathTemplate::compile("#1:title #2", 2)
+                .substitute("doctor WHO s01", &["doctor WHO", "s01"]),
+            PathBuf::from("Doctor Who s01")
+        );
+        assert_eq!(
+            DestinationPathTemplate::compile("#1:bogus", 1).substitute("rs", &["rs"]),
+            PathBuf::from("rs:bogus")
+        );
+    }
+
+    #[test]
+    fn substitute_slices() {
+        let pattern = DestinationPathTemplate::compile("#1[0..3]-#1[2..100]-#1[..2]", 1);
+        assert_eq!(
+            pattern.substitute("abcdef", &["abcdef"]),
+            PathBuf::from("abc-cdef-ab")
+        );
+        assert_eq!(
+            DestinationPathTemplate::compile("#1[0..8].#2[1..", 2)
+                .substitute("e3b0c44298fc1c14.txt", &["e3b0c44298fc1c14", "txt"]),
+            PathBuf::from("e3b0c442.txt[1..")
+        );
+    }
+
+    #[test]
+    fn whole_filename_marker() {
+        assert_eq!(
+            DestinationPathTemplate::compile("backup/#0.bak", 0),
+            DestinationPathTemplate {
+                directory: "backup/".into(),
+                markers: vec![0.into()],
+                literal_blocks: blocks(&["", ".bak"]),
+                max_literal_index: 0
+            }
+        );
+        assert_eq!(
+            DestinationPathTemplate::compile("#01#10#0", 10),
+            DestinationPathTemplate {
+                directory: "".into(),
+                markers: vec![10.into(), 0.into()],
+                literal_blocks: blocks(&["#01", "", ""]),
+                max_literal_index: 1
+            }
+        );
+
+        let pattern = DestinationPathTemplate::compile("backup/#0.bak", 1);
+        assert_eq!(
+            pattern.substitute("original", &["orig"]),
+            PathBuf::from("backup/original.bak")
+        );
+        assert_eq!(
+            DestinationPathTemplate::compile("#1-#0:upper", 1).substitute("a.rs", &["a"]),
+            PathBuf::from("a-A.RS")
+        );
+    }
+
+    #[test]
+    fn into_directory() {
+        let template = DestinationPathTemplate::compile("moved/", 2);
+        assert_eq!(template, DestinationPathTemplate::compile("moved/#0", 2));
+        assert_eq!(template.directory, PathBuf::from("moved/"));
+        assert_eq!(
+            template.substitute("Harry", &["arry", ""]),
+            PathBuf::from("moved/Harry")
+        );
+        assert_eq!(
+            DestinationPathTemplate::compile("/", 0).substitute("root", &[]),
+            PathBuf::from("/root")
+        );
+    }
+
+    #[test]
+    fn max_referenced_index() {
+        for (pattern, max_marker_index, expected) in [
+            ("file", 2, 0),
+            ("#1-#2", 2, 2),
+            ("#3", 2, 3),
+            ("#2-#1:03", 2, 2),
+            ("#100", 10, 10),
+            ("#100", 1, 1),
+            ("#0", 0, 0),
+            ("#01", 0, 1),
+            ("##5#", 5, 5),
+            ("#99999999999999999999999", 1, usize::MAX),
+            ("dir#9/#1", 1, 1),
+        ] {
+            assert_eq!(
+                DestinationPathTemplate::compile(pattern, max_marker_index).max_referenced_index(),
+                expected,
+                "{pattern}"
+            );
+        }
+    }
+
     #[test]
     #[should_panic]
     fn substitute_out_of_range() {
-        DestinationPathTemplate::compile("#1", 1).substitute(&[]);
+        DestinationPathTemplate::compile("#1", 1).substitute("", &[]);
+    }
+
+    #[test]
+    fn try_substitute_out_of_range() {
+        assert_eq!(
+            DestinationPathTemplate::compile("#1", 1).try_substitute("", &[]),
+            Err(SubstituteError {
+                marker_index: 1,
+                fragments_number: 0
+            })
+        );
+        let error = DestinationPathTemplate::compile("#0-#3-#2", 3)
+            .try_substitute("file", &["a", "b"])
+            .unwrap_err();
+        assert_eq!(error.marker_index, 3);
+        assert_eq!(
+            error.to_string(),
+            "Marker #3 refers to a missing fragment: only 2 fragments are given"
+        );
+        assert_eq!(
+            DestinationPathTemplate::compile("#0", 0).try_substitute("file", &[]),
+            Ok(PathBuf::from("file"))
+        );
     }
 
     #[test]
     fn substitute() {
         let pattern = DestinationPathTemplate::compile("dir/file_#2.#1", 2);
         assert_eq!(
-            pattern.substitute(&["meow", "oink"]),
+            pattern.substitute("meow.oink", &["meow", "oink"]),
             PathBuf::from("dir/file_oink.meow")
         );
         assert_eq!(
-            pattern.substitute(&["#2", "#1"]),
+            pattern.substitute("#2.#1", &["#2", "#1"]),
             PathBuf::from("dir/file_#1.#2")
         );
     }
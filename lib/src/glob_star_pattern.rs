@@ -1,6 +1,20 @@
 use std::fmt::Display;
+use std::ops::Range;
 use std::str::pattern::Pattern;
 
+/// Defines which fragments are captured by wildcards when a string can be matched
+/// in several ways, e.g. `a.rs.rs.rs` matched against `*.rs*.rs`
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum MatchingMode {
+    /// Each wildcard captures the shortest possible fragment, leaving the rest to the last one:
+    /// `a.rs.rs.rs` gives `a` and `.rs`
+    #[default]
+    Lazy,
+    /// Each wildcard captures the longest possible fragment, leaving the rest to the last one:
+    /// `a.rs.rs.rs` gives `a.rs` and an empty string
+    Greedy,
+}
+
 /// A compiled pattern. Like `glob` pattern, but only '*' wildcard is supported.
 /// `*` matches any (possibly empty) sequence of characters.
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -8,9 +22,24 @@ pub struct GlobStarPattern {
     /// Blocks of text to be matched literally
     /// The represented pattern is equivalent to `literal_blocks.join("*")`
     literal_blocks: Vec<String>,
+    mode: MatchingMode,
 }
 
 impl GlobStarPattern {
+    /// Returns the same pattern matching strings in the given `mode`
+    ///
+    /// # Examples
+    /// ```
+    /// use mmv_lib::{GlobStarPattern, MatchingMode};
+    /// let pattern = GlobStarPattern::from("*.rs*.rs");
+    /// assert_eq!(pattern.match_string("a.rs.rs.rs"), vec!["a", ".rs"].into());
+    /// let greedy_pattern = pattern.with_mode(MatchingMode::Greedy);
+    /// assert_eq!(greedy_pattern.match_string("a.rs.rs.rs"), vec!["a.rs", ""].into());
+    /// ```
+    pub fn with_mode(self, mode: MatchingMode) -> Self {
+        Self { mode, ..self }
+    }
+
     /// Returns the number of '*'-wildcards in the pattern
     ///
     /// # Examples
@@ -23,6 +52,14 @@ impl GlobStarPattern {
         self.literal_blocks.len() - 1
     }
 
+    /// Iterates over characters of the pattern as it's written, without joining it into a string
+    pub(crate) fn chars(&self) -> impl Iterator<Item = char> + '_ {
+        self.literal_blocks
+            .iter()
+            .enumerate()
+            .flat_map(|(index, block)| (index > 0).then_some('*').into_iter().chain(block.chars()))
+    }
+
     /// Matches the `string` to the pattern.
     /// It the string matches, returns `Vec<&str>` of `string`'s substrings matched by wildcards.
     /// Otherwise returns `None`.
@@ -43,32 +80,102 @@ impl GlobStarPattern {
     ///     vec!["is_the_best", "rs"].into(),
     /// );
     /// ```
-    pub fn match_string<'a>(&self, mut string: &'a str) -> Option<Vec<&'a str>> {
-        string = self.literal_blocks[0].strip_prefix_of(string)?;
-        if self.wildcards_number() == 0 {
-            return if string.is_empty() {
-                Some(vec![])
-            } else {
-                None
-            };
+    pub fn match_string<'a>(&self, string: &'a str) -> Option<Vec<&'a str>> {
+        Some(
+            self.match_string_spans(string)?
+                .into_iter()
+                .map(|span| &string[span])
+                .collect(),
+        )
+    }
+
+    /// Matches the `path` to the pattern treating `/` as a separator of path components:
+    /// unlike [`match_string`][GlobStarPattern::match_string], `*` never matches `/`, so
+    /// the path must have the same number of components as the pattern and each component
+    /// is matched separately. Returns substrings matched by wildcards in order of the wildcards.
+    ///
+    /// # Examples
+    /// ```
+    /// use mmv_lib::GlobStarPattern;
+    /// let pattern = GlobStarPattern::from("a/*/c");
+    /// assert_eq!(pattern.match_path("a/b/c"), vec!["b"].into());
+    /// assert!(pattern.match_path("a/b/x/c").is_none());
+    /// assert_eq!(pattern.match_string("a/b/x/c"), vec!["b/x"].into());
+    /// ```
+    pub fn match_path<'a>(&self, path: &'a str) -> Option<Vec<&'a str>> {
+        let pattern = self.to_string();
+        let pattern_components = pattern.split('/').collect::<Vec<_>>();
+        let path_components = path.split('/').collect::<Vec<_>>();
+        if pattern_components.len() != path_components.len() {
+            return None;
         }
         let mut result = Vec::with_capacity(self.wildcards_number());
-        for block in self
-            .literal_blocks
-            .iter()
-            .skip(1)
-            .take(self.wildcards_number() - 1)
+        for (pattern_component, path_component) in
+            pattern_components.into_iter().zip(path_components)
         {
-            let block_match_begin = string.find(block)?;
-            result.push(&string[..block_match_begin]);
-            string = &string[block_match_begin + block.len()..];
+            result.extend(
+                GlobStarPattern::from(pattern_component)
+                    .with_mode(self.mode)
+                    .match_string(path_component)?,
+            );
         }
-        result.push(
-            self.literal_blocks
-                .last()
-                .unwrap()
-                .strip_suffix_of(string)?,
-        );
+        Some(result)
+    }
+
+    /// Like [`match_string`][GlobStarPattern::match_string], but returns byte ranges of
+    /// `string` matched by wildcards instead of substrings.
+    ///
+    /// # Examples
+    /// ```
+    /// use mmv_lib::GlobStarPattern;
+    /// let png_pattern = GlobStarPattern::from("*.png");
+    /// assert_eq!(png_pattern.match_string_spans("ferris.png"), vec![0..6].into());
+    /// assert_eq!(png_pattern.match_string_spans(".png"), vec![0..0].into());
+    /// assert!(png_pattern.match_string_spans("ferris.jpg").is_none());
+    /// ```
+    pub fn match_string_spans(&self, string: &str) -> Option<Vec<Range<usize>>> {
+        let first_block = &self.literal_blocks[0];
+        if !first_block.is_prefix_of(string) {
+            return None;
+        }
+        let mut position = first_block.len();
+        if self.wildcards_number() == 0 {
+            return (position == string.len()).then(Vec::new);
+        }
+        let last_block = self.literal_blocks.last().unwrap();
+        let end = string.len().checked_sub(last_block.len())?;
+        if end < position || !last_block.is_suffix_of(string) {
+            return None;
+        }
+        let interior_blocks = &self.literal_blocks[1..self.wildcards_number()];
+        let mut interior_matches = Vec::with_capacity(interior_blocks.len());
+        match self.mode {
+            MatchingMode::Lazy => {
+                let mut search_begin = position;
+                for block in interior_blocks {
+                    let block_match_begin =
+                        search_begin + string[search_begin..end].find(block.as_str())?;
+                    search_begin = block_match_begin + block.len();
+                    interior_matches.push(block_match_begin..search_begin);
+                }
+            }
+            MatchingMode::Greedy => {
+                let mut search_end = end;
+                for block in interior_blocks.iter().rev() {
+                    let block_match_begin =
+                        position + string[position..search_end].rfind(block.as_str())?;
+                    interior_matches.push(block_match_begin..block_match_begin + block.len());
+                    search_end = block_match_begin;
+                }
+                interior_matches.reverse();
+            }
+        }
+        let mut result = Vec::with_capacity(self.wildcards_number());
+        for block_match in interior_matches {
+            result.push(position..block_match.start);
+            position = block_match.end;
+        }
+        result.push(position..end);
         debug_assert_eq!(result.len(), self.wildcards_number());
         Some(result)
     }
@@ -76,7 +183,7 @@ impl GlobStarPattern {
 
 #[cfg(test)]
 mod test_match_string {
-    use super::GlobStarPattern;
+    use super::{GlobStarPattern, MatchingMode};
 
     fn check(pattern: &str, test_data: &[(&str, Option<Vec<&str>>)]) {
         let compiled_pattern = GlobStarPattern::from(pattern);
@@ -161,6 +268,30 @@ mod test_match_string {
         );
     }
 
+    #[test]
+    #[allow(clippy::single_range_in_vec_init)]
+    fn spans() {
+        let pattern = GlobStarPattern::from("rust_*_language.*");
+        assert_eq!(
+            pattern.match_string_spans("rust_is_the_best_language.rs"),
+            Some(vec![5..16, 26..28])
+        );
+        assert_eq!(
+            pattern.match_string_spans("rust__language."),
+            Some(vec![5..5, 15..15])
+        );
+        assert_eq!(pattern.match_string_spans("rust_language.rs"), None);
+        assert_eq!(
+            GlobStarPattern::from("*.png").match_string_spans(".png"),
+            Some(vec![0..0])
+        );
+        assert_eq!(GlobStarPattern::from("a*a").match_string_spans("a"), None);
+        assert_eq!(
+            GlobStarPattern::from("ж*ы").match_string_spans("жабы"),
+            Some(vec![2..6])
+        );
+    }
+
     #[test]
     fn repeated_pattern() {
         check(
@@ -175,12 +306,90 @@ mod test_match_string {
             ],
         )
     }
+
+    #[test]
+    fn match_path() {
+        let check_path = |pattern: &str, path: &str, expected: Option<Vec<&str>>| {
+            assert_eq!(
+                GlobStarPattern::from(pattern).match_path(path),
+                expected,
+                "{pattern} vs {path}"
+            );
+        };
+        check_path("a/*/c", "a/b/c", Some(vec!["b"]));
+        check_path("a/*/c", "a/b/x/c", None);
+        check_path("a/*/c", "a//c", Some(vec![""]));
+        check_path("a/*/c", "a/b/d", None);
+        check_path("*.rs", "src/main.rs", None);
+        check_path("src/*/*.rs", "src/bin/tool.rs", Some(vec!["bin", "tool"]));
+        check_path("*/*", "a/b", Some(vec!["a", "b"]));
+        check_path("*-*/x", "a-b-c/x", Some(vec!["a", "b-c"]));
+        check_path("/abs/*", "/abs/file", Some(vec!["file"]));
+        check_path("no-wildcards", "no-wildcards", Some(vec![]));
+        assert_eq!(
+            GlobStarPattern::from("*-*/x")
+                .with_mode(MatchingMode::Greedy)
+                .match_path("a-b-c/x"),
+            Some(vec!["a-b", "c"])
+        );
+    }
+
+    #[test]
+    fn matching_modes() {
+        fn check_modes(pattern: &str, string: &str, lazy: Vec<&str>, greedy: Vec<&str>) {
+            let pattern = GlobStarPattern::from(pattern);
+            assert_eq!(pattern.match_string(string), Some(lazy));
+            assert_eq!(
+                pattern.with_mode(MatchingMode::Greedy).match_string(string),
+                Some(greedy)
+            );
+        }
+
+        check_modes("*.rs*.rs", "a.rs.rs.rs", vec!["a", ".rs"], vec!["a.rs", ""]);
+        check_modes("*.rs*.rs", "file.rs.rs", vec!["file", ""], vec!["file", ""]);
+        check_modes(
+            "*-*-*",
+            "a-b-c-d",
+            vec!["a", "b", "c-d"],
+            vec!["a-b", "c", "d"],
+        );
+        check_modes(
+            "x*ab*ab*y",
+            "xabababy",
+            vec!["", "", "ab"],
+            vec!["ab", "", ""],
+        );
+        check_modes("*aa*", "aaa", vec!["", "a"], vec!["a", ""]);
+    }
+
+    #[test]
+    fn matching_modes_agree_on_unambiguous_strings() {
+        for (pattern, string) in [
+            ("*.jpg", "filename.jpg"),
+            ("rust_*_language.*", "rust_is_the_best_language.rs"),
+            ("IMG*:*:*2010.png", "IMG12:30:002010.png"),
+            ("*.*", "42.rs"),
+            ("*.rs*.rs", "file.rs42.rs"),
+            ("a*b", "ac"),
+        ] {
+            let pattern = GlobStarPattern::from(pattern);
+            assert_eq!(
+                pattern.match_string(string),
+                pattern
+                    .clone()
+                    .with_mode(MatchingMode::Greedy)
+                    .match_string(string),
+                "{pattern} vs {string}"
+            );
+        }
+    }
 }
 
 impl From<&str> for GlobStarPattern {
     fn from(string: &str) -> Self {
         Self {
             literal_blocks: string.split('*').map(str::to_string).collect(),
+            mode: MatchingMode::default(),
         }
     }
 }
@@ -1,9 +1,22 @@
 #![feature(pattern)]
 
+mod brace_expansion;
 mod destination_path_template;
 mod glob_star_pattern;
+mod marker;
+mod matcher;
+mod plan;
+mod regex_pattern;
 mod source_path_pattern;
+mod variable_expansion;
 
-pub use destination_path_template::DestinationPathTemplate;
-pub use glob_star_pattern::GlobStarPattern;
-pub use source_path_pattern::SourcePathPattern;
+pub use destination_path_template::{DestinationPathTemplate, SubstituteError};
+pub use glob_star_pattern::{GlobStarPattern, MatchingMode};
+pub use matcher::Matcher;
+pub use plan::{
+    find_collisions, plan_entry_moves, plan_moves, plan_moves_from_paths, plan_moves_iter,
+    PlannedEntry, PlannedMoves,
+};
+pub use regex_pattern::RegexPattern;
+pub use source_path_pattern::{EntryDecision, MatchOptions, MatchingEntries, SourcePathPattern};
+pub use variable_expansion::UnknownVariableError;
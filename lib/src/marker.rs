@@ -0,0 +1,460 @@
+use std::str::pattern::Pattern;
+
+/// A marker of a [`DestinationPathTemplate`][crate::DestinationPathTemplate], e.g. `#1`, `#2:03`,
+/// `#1:lower`, `#1[0..8]`, `#1+100` or `#-1`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub(crate) struct Marker {
+    /// Index of the substituted fragment, starting from `1`. Index `0` means the whole filename.
+    /// Negative indices count fragments from the end, e.g. `-1` is the last fragment.
+    pub index: i16,
+    /// Modifiers applied to the fragment in order of appearance
+    pub modifiers: Vec<Modifier>,
+}
+
+/// Transformation of a fragment specified after a colon, e.g. `:03` in `#1:03`,
+/// a slice in square brackets, e.g. `[0..8]` in `#1[0..8]`, or an offset, e.g. `+100` in `#1+100`
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub(crate) enum Modifier {
+    /// `[BEGIN..END]` takes characters (not bytes) of a fragment from `BEGIN` to `END`
+    /// exclusively. Both bounds are optional and are clamped to the fragment length.
+    Slice { begin: usize, end: Option<usize> },
+    /// `:0WIDTH` pads numeric (i.e. consisting of ASCII digits only) fragments with zeros
+    /// and leaves other fragments as is. `:WIDTH` pads any fragment with spaces.
+    /// The width is limited by `u16::MAX`, since `format!` doesn't support bigger ones.
+    Pad { width: u16, with_zeros: bool },
+    /// `:lower` converts a fragment to lowercase
+    Lower,
+    /// `:upper` converts a fragment to uppercase
+    Upper,
+    /// `:title` capitalizes the first letter of every word and lowercases others.
+    /// Words are separated by non-alphanumeric characters.
+    Title,
+    /// `+N` or `:add=N` adds `N` to an integer fragment and leaves other fragments as is.
+    /// `N` can be negative in the latter form, and so can be the result. A zero-padded fragment
+    /// keeps its width, e.g. `007` plus `5` is `012`.
+    Add { offset: i64 },
+}
+
+impl From<u8> for Marker {
+    fn from(index: u8) -> Self {
+        Self {
+            index: index.into(),
+            modifiers: vec![],
+        }
+    }
+}
+
+impl Marker {
+    /// Parses a marker at the beginning of `string`, which is a text following `#`.
+    /// Returns the marker and the length of its text, or `None` if there is no valid marker.
+    /// A modifier which cannot be parsed isn't a part of the marker.
+    /// A negative marker, e.g. `-1`, refers to a fragment counting from the end, so its absolute
+    /// value must not be greater than `max_marker_index` as well.
+    pub fn parse(string: &str, max_marker_index: u8) -> Option<(Self, usize)> {
+        let (sign, unsigned) = match string.strip_prefix('-') {
+            Some(unsigned) => (-1, unsigned),
+            None => (1, string),
+        };
+        let index = (1..=max_marker_index)
+            .rev()
+            .find(|marker_index| marker_index.to_string().is_prefix_of(unsigned))
+            .map(|marker_index| sign * i16::from(marker_index))
+            .or_else(|| {
+                let is_whole_filename_marker = sign > 0
+                    && string.starts_with('0')
+                    && !string[1..].starts_with(|symbol: char| symbol.is_ascii_digit());
+                is_whole_filename_marker.then_some(0)
+            })?;
+        let mut length = index.to_string().len();
+        let mut modifiers = vec![];
+        while let Some((modifier, modifier_length)) = Modifier::parse(&string[length..]) {
+            modifiers.push(modifier);
+            length += modifier_length;
+        }
+        Some((Self { index, modifiers }, length))
+    }
+
+    /// Pushes the corresponding fragment with applied modifiers to `output`.
+    ///
+    /// # Panics
+    /// Panics if `fragments_values` doesn't contain the fragment, see [`Marker::fragment_exists`].
+    pub fn substitute(
+        &self,
+        source_filename: &str,
+        fragments_values: &[impl AsRef<str>],
+        output: &mut String,
+    ) {
+        let mut fragment = match self.index {
+            0 => source_filename,
+            index if index > 0 => fragments_values[index as usize - 1].as_ref(),
+            index => {
+                fragments_values[fragments_values.len() - index.unsigned_abs() as usize].as_ref()
+            }
+        }
+        .to_string();
+        for modifier in &self.modifiers {
+            fragment = modifier.apply(&fragment);
+        }
+        *output += &fragment;
+    }
+}
+
+impl Marker {
+    /// Checks whether the fragment the marker refers to is among `fragments_number` fragments
+    pub fn fragment_exists(&self, fragments_number: usize) -> bool {
+        self.index.unsigned_abs() as usize <= fragments_number
+    }
+}
+
+impl Modifier {
+    /// Parses a modifier at the beginning of `string`, i.e. either a text following `:`
+    /// or a slice in square brackets. Returns the modifier and the length of its text.
+    /// Modifier names must not be followed by other letters, e.g. `:lowercase` isn't a modifier.
+    fn parse(string: &str) -> Option<(Self, usize)> {
+        if let Some(slice) = string.strip_prefix('[') {
+            return Self::parse_slice(slice).map(|(modifier, length)| (modifier, length + 1));
+        }
+        if let Some(offset) = string.strip_prefix('+') {
+            let digits_length = leading_digits_length(offset);
+            let offset = offset[..digits_length].parse().ok()?;
+            return Some((Self::Add { offset }, digits_length + 1));
+        }
+        let string = string.strip_prefix(':')?;
+        if let Some(offset) = string.strip_prefix("add=") {
+            let sign_length = usize::from(offset.starts_with('-'));
+            let number_length = sign_length + leading_digits_length(&offset[sign_length..]);
+            let offset = offset[..number_length].parse().ok()?;
+            return Some((Self::Add { offset }, number_length + 5));
+        }
+        let digits_length = leading_digits_length(string);
+        if digits_length > 0 {
+            let digits = &string[..digits_length];
+            let width = digits.parse().ok().filter(|width| *width > 0)?;
+            return Some((
+                Self::Pad {
+                    width,
+                    with_zeros: digits.starts_with('0'),
+                },
+                digits_length + 1,
+            ));
+        }
+        let word_length = string
+            .find(|symbol: char| !symbol.is_ascii_alphabetic())
+            .unwrap_or(string.len());
+        let modifier = match &string[..word_length] {
+            "lower" => Self::Lower,
+            "upper" => Self::Upper,
+            "title" => Self::Title,
+            _ => return None,
+        };
+        Some((modifier, word_length + 1))
+    }
+
+    /// Parses `BEGIN..END]`, i.e. a text following `[`
+    fn parse_slice(string: &str) -> Option<(Self, usize)> {
+        let parse_bound = |string: &str| {
+            let digits_length = leading_digits_length(string);
+            let bound = match digits_length {
+                0 => None,
+                _ => Some(string[..digits_length].parse().ok()?),
+            };
+            Some((bound, digits_length))
+        };
+        let (begin, begin_length) = parse_bound(string)?;
+        let string = string[begin_length..].strip_prefix("..")?;
+        let (end, end_length) = parse_bound(string)?;
+        string[end_length..].strip_prefix(']')?;
+        Some((
+            Self::Slice {
+                begin: begin.unwrap_or(0),
+                end,
+            },
+            begin_length + end_length + 3,
+        ))
+    }
+
+    fn apply(&self, fragment: &str) -> String {
+        match *self {
+            Self::Pad { width, with_zeros } => {
+                let width = usize::from(width);
+                let is_numeric =
+                    !fragment.is_empty() && fragment.bytes().all(|byte| byte.is_ascii_digit());
+                if !with_zeros {
+                    format!("{fragment:>width$}")
+                } else if is_numeric {
+                    format!("{fragment:0>width$}")
+                } else {
+                    fragment.to_string()
+                }
+            }
+            Self::Slice { begin, end } => fragment
+                .chars()
+                .take(end.unwrap_or(usize::MAX))
+                .skip(begin)
+                .collect(),
+            Self::Add { offset } => {
+                let Some(sum) = fragment
+                    .parse::<i64>()
+                    .ok()
+                    .and_then(|number| number.checked_add(offset))
+                else {
+                    return fragment.to_string();
+                };
+                let width = if fragment.starts_with('0') {
+                    fragment.len()
+                } else {
+                    0
+                };
+                format!("{sum:0width$}")
+            }
+            Self::Lower => fragment.to_lowercase(),
+            Self::Upper => fragment.to_uppercase(),
+            Self::Title => {
+                let mut result = String::with_capacity(fragment.len());
+                let mut is_word_begin = true;
+                for symbol in fragment.chars() {
+                    if is_word_begin {
+                        result.extend(symbol.to_uppercase());
+                    } else {
+                        result.extend(symbol.to_lowercase());
+                    }
+                    is_word_begin = !symbol.is_alphanumeric();
+                }
+                result
+            }
+        }
+    }
+}
+
+fn leading_digits_length(string: &str) -> usize {
+    string
+        .find(|symbol: char| !symbol.is_ascii_digit())
+        .unwrap_or(string.len())
+}
+
+#[cfg(test)]
+mod test_marker {
+    use super::{Marker, Modifier};
+
+    #[test]
+    fn parse() {
+        assert_eq!(Marker::parse("1", 1), Some((1.into(), 1)));
+        assert_eq!(Marker::parse("12", 12), Some((12.into(), 2)));
+        assert_eq!(Marker::parse("12", 3), Some((1.into(), 1)));
+        assert_eq!(Marker::parse("3", 2), None);
+        assert_eq!(Marker::parse("", 2), None);
+        assert_eq!(Marker::parse("0", 0), Some((0.into(), 1)));
+        assert_eq!(Marker::parse("0.bak", 2), Some((0.into(), 1)));
+        assert_eq!(Marker::parse("01", 2), None);
+        assert_eq!(Marker::parse("10", 10), Some((10.into(), 2)));
+        assert_eq!(Marker::parse("10", 9), Some((1.into(), 1)));
+        let negative = |index| Marker {
+            index,
+            modifiers: vec![],
+        };
+        assert_eq!(Marker::parse("-1", 1), Some((negative(-1), 2)));
+        assert_eq!(Marker::parse("-12", 12), Some((negative(-12), 3)));
+        assert_eq!(Marker::parse("-12", 3), Some((negative(-1), 2)));
+        assert_eq!(Marker::parse("-3", 2), None);
+        assert_eq!(Marker::parse("-0", 2), None);
+        assert_eq!(Marker::parse("-", 2), None);
+        assert_eq!(
+            Marker::parse("-1:upper", 1),
+            Some((
+                Marker {
+                    index: -1,
+                    modifiers: vec![Modifier::Upper]
+                },
+                8
+            ))
+        );
+        assert_eq!(
+            Marker::parse("1:03.png", 1),
+            Some((
+                Marker {
+                    index: 1,
+                    modifiers: vec![Modifier::Pad {
+                        width: 3,
+                        with_zeros: true
+                    }]
+                },
+                4
+            ))
+        );
+        assert_eq!(
+            Marker::parse("2:10:", 2),
+            Some((
+                Marker {
+                    index: 2,
+                    modifiers: vec![Modifier::Pad {
+                        width: 10,
+                        with_zeros: false
+                    }]
+                },
+                4
+            ))
+        );
+        assert_eq!(Marker::parse("1:0", 1), Some((1.into(), 1)));
+        assert_eq!(Marker::parse("1:65536", 1), Some((1.into(), 1)));
+        assert_eq!(Marker::parse("1:070000.txt", 1), Some((1.into(), 1)));
+        assert_eq!(
+            Marker::parse("1:65535", 1),
+            Some((
+                Marker {
+                    index: 1,
+                    modifiers: vec![Modifier::Pad {
+                        width: u16::MAX,
+                        with_zeros: false
+                    }]
+                },
+                7
+            ))
+        );
+        assert_eq!(Marker::parse("1:x3", 1), Some((1.into(), 1)));
+        assert_eq!(
+            Marker::parse("12:lower", 12),
+            Some((
+                Marker {
+                    index: 12,
+                    modifiers: vec![Modifier::Lower]
+                },
+                8
+            ))
+        );
+        assert_eq!(
+            Marker::parse("1:upper:05-", 1),
+            Some((
+                Marker {
+                    index: 1,
+                    modifiers: vec![
+                        Modifier::Upper,
+                        Modifier::Pad {
+                            width: 5,
+                            with_zeros: true
+                        }
+                    ]
+                },
+                10
+            ))
+        );
+        assert_eq!(Marker::parse("1:bogus", 1), Some((1.into(), 1)));
+        assert_eq!(
+            Marker::parse("1+100.png", 1),
+            Some((
+                Marker {
+                    index: 1,
+                    modifiers: vec![Modifier::Add { offset: 100 }]
+                },
+                5
+            ))
+        );
+        assert_eq!(
+            Marker::parse("1:add=-3", 1),
+            Some((
+                Marker {
+                    index: 1,
+                    modifiers: vec![Modifier::Add { offset: -3 }]
+                },
+                8
+            ))
+        );
+        for malformed in ["1+", "1+x", "1+-3", "1:add=", "1:add=-", "1:add=x"] {
+            assert_eq!(Marker::parse(malformed, 1), Some((1.into(), 1)));
+        }
+        assert_eq!(Marker::parse("1:lowercase", 1), Some((1.into(), 1)));
+        assert_eq!(Marker::parse("1:upper2", 1).unwrap().1, 7);
+        assert_eq!(
+            Marker::parse("1[0..8]:upper[3..]", 1),
+            Some((
+                Marker {
+                    index: 1,
+                    modifiers: vec![
+                        Modifier::Slice {
+                            begin: 0,
+                            end: Some(8)
+                        },
+                        Modifier::Upper,
+                        Modifier::Slice {
+                            begin: 3,
+                            end: None
+                        }
+                    ]
+                },
+                18
+            ))
+        );
+        assert_eq!(
+            Marker::parse("1[..]", 1),
+            Some((
+                Marker {
+                    index: 1,
+                    modifiers: vec![Modifier::Slice {
+                        begin: 0,
+                        end: None
+                    }]
+                },
+                5
+            ))
+        );
+        for malformed in [
+            "1[",
+            "1[0..8",
+            "1[0.8]",
+            "1[a..b]",
+            "1[-1..]",
+            "1[0..99999999999999999999999]",
+        ] {
+            assert_eq!(Marker::parse(malformed, 1), Some((1.into(), 1)));
+        }
+    }
+
+    #[test]
+    fn substitute() {
+        fn check(marker: &str, fragment: &str, expected: &str) {
+            let (marker, _) = Marker::parse(marker, 1).unwrap();
+            let mut output = String::new();
+            marker.substitute("", &[fragment], &mut output);
+            assert_eq!(output, expected);
+        }
+
+        check("1", "7", "7");
+        check("1:03", "7", "007");
+        check("1:03", "1234", "1234");
+        check("1:03", "x7", "x7");
+        check("1:03", "", "");
+        check("1:3", "x7", " x7");
+        check("1:3", "", "   ");
+        check("1:upper", "rs", "RS");
+        check("1:lower", "JPG", "jpg");
+        check("1:title", "the DOCTOR's tardis-2", "The Doctor'S Tardis-2");
+        check("1:lower:title", "ÉCOLE", "École");
+        check("1:upper:4", "rs", "  RS");
+        check("1[0..3]", "abcdef", "abc");
+        check("1[3..]", "abcdef", "def");
+        check("1[..2]", "abcdef", "ab");
+        check("1[4..100]", "abcdef", "ef");
+        check("1[10..]", "abcdef", "");
+        check("1[4..2]", "abcdef", "");
+        check("1[1..3]", "жабы", "аб");
+        check("1+5", "10", "15");
+        check("1+5", "abc", "abc");
+        check("1+5", "", "");
+        check("1+5", "007", "012");
+        check("1+100", "7", "107");
+        check("1:add=-20", "7", "-13");
+        check("1:add=-1", "010", "009");
+        check("1+1:03", "7", "008");
+        check("1+1", "9223372036854775807", "9223372036854775807");
+    }
+
+    #[test]
+    fn substitute_negative() {
+        let fragments = ["a", "b", "c"];
+        for (marker, expected) in [("-1", "c"), ("-2", "b"), ("-3", "a"), ("-1:upper", "C")] {
+            let (marker, _) = Marker::parse(marker, 3).unwrap();
+            let mut output = String::new();
+            marker.substitute("", &fragments, &mut output);
+            assert_eq!(output, expected);
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use std::fmt::Debug;
+
+use crate::{glob_star_pattern::GlobStarPattern, regex_pattern::RegexPattern};
+
+/// Something filenames can be matched against, capturing fragments for the destination markers.
+/// Filenames are matched against patterns of [`SourcePathPattern`][crate::SourcePathPattern]
+/// through this trait, so a custom matcher can be used as well, see
+/// [`from_matcher`][crate::SourcePathPattern::from_matcher].
+pub trait Matcher: Debug + Send + Sync {
+    /// Matches the whole `string`. If it matches, returns its substrings captured by wildcards,
+    /// exactly [`wildcards_number`][Matcher::wildcards_number] of them. Otherwise returns `None`.
+    fn match_string<'a>(&self, string: &'a str) -> Option<Vec<&'a str>>;
+
+    /// Returns the number of fragments captured by a successful match
+    fn wildcards_number(&self) -> usize;
+
+    /// Whether the matcher explicitly refers to hidden files, i.e. names beginning with `.`.
+    /// Otherwise they are skipped unless hidden files are included, see
+    /// [`MatchOptions`][crate::MatchOptions].
+    fn matches_hidden(&self) -> bool {
+        false
+    }
+}
+
+impl Matcher for GlobStarPattern {
+    fn match_string<'a>(&self, string: &'a str) -> Option<Vec<&'a str>> {
+        GlobStarPattern::match_string(self, string)
+    }
+
+    fn wildcards_number(&self) -> usize {
+        GlobStarPattern::wildcards_number(self)
+    }
+
+    fn matches_hidden(&self) -> bool {
+        self.chars().next() == Some('.')
+    }
+}
+
+impl Matcher for RegexPattern {
+    fn match_string<'a>(&self, string: &'a str) -> Option<Vec<&'a str>> {
+        RegexPattern::match_string(self, string)
+    }
+
+    fn wildcards_number(&self) -> usize {
+        self.captures_number()
+    }
+
+    /// A regular expression must begin with `\.` or `^\.`
+    fn matches_hidden(&self) -> bool {
+        let pattern = self.as_str();
+        pattern.starts_with("\\.") || pattern.starts_with("^\\.")
+    }
+}
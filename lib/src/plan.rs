@@ -0,0 +1,377 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use chrono::Local;
+
+use crate::{
+    source_path_pattern::WalkDecision, DestinationPathTemplate, EntryDecision, MatchOptions,
+    SourcePathPattern,
+};
+
+/// Calculates `(source, destination)` pairs for all files matching `source`: each destination
+/// is `template` with substituted fragments of the corresponding source filename.
+/// Both paths are relative to `working_directory` unless patterns are absolute.
+/// Performs no filesystem mutation: the files are only listed, not moved.
+///
+/// # Examples
+/// ```
+/// use mmv_lib::{plan_moves, DestinationPathTemplate, SourcePathPattern};
+/// use std::{path::{Path, PathBuf}, str::FromStr};
+/// let moves = plan_moves(
+///     &SourcePathPattern::from_str("bin/*sh").unwrap(),
+///     &DestinationPathTemplate::compile("shells/#1-shell", 1),
+///     Path::new("/usr"),
+/// )
+/// .unwrap();
+/// assert!(moves.contains(&(PathBuf::from("bin/bash"), PathBuf::from("shells/ba-shell"))));
+/// assert!(Path::new("/usr/bin/bash").exists());
+/// ```
+pub fn plan_moves(
+    source: &SourcePathPattern,
+    template: &DestinationPathTemplate,
+    working_directory: &Path,
+) -> anyhow::Result<Vec<(PathBuf, PathBuf)>> {
+    Ok(plan_entry_moves(
+        source,
+        template,
+        working_directory,
+        &MatchOptions::default(),
+    )?
+    .moves)
+}
+
+/// Moves calculated by [`plan_entry_moves`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PlannedMoves {
+    /// `(source, destination)` pairs
+    pub moves: Vec<(PathBuf, PathBuf)>,
+    /// Paths of the entries which couldn't be matched because their names aren't valid UTF-8,
+    /// see [`MatchingEntries`][crate::MatchingEntries]
+    pub non_utf8: Vec<PathBuf>,
+}
+
+/// Like [`plan_moves`], but the set of moved entries is controlled by `options`,
+/// see [`MatchOptions`].
+pub fn plan_entry_moves(
+    source: &SourcePathPattern,
+    template: &DestinationPathTemplate,
+    working_directory: &Path,
+    options: &MatchOptions,
+) -> anyhow::Result<PlannedMoves> {
+    let mut planned_moves = PlannedMoves::default();
+    for entry in plan_moves_iter(source, template, working_directory, options) {
+        match entry? {
+            PlannedEntry::Move(source_path, destination_path) => {
+                planned_moves.moves.push((source_path, destination_path))
+            }
+            PlannedEntry::NonUtf8(path) => planned_moves.non_utf8.push(path),
+            PlannedEntry::Skipped(..) => {}
+        }
+    }
+    Ok(planned_moves)
+}
+
+/// An entry produced by [`plan_moves_iter`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PlannedEntry {
+    /// `(source, destination)` pair of a matching entry
+    Move(PathBuf, PathBuf),
+    /// Path of an entry which couldn't be matched because its name isn't valid UTF-8
+    NonUtf8(PathBuf),
+    /// Path of an entry which isn't matched with the reason, never [`EntryDecision::Matched`]
+    /// or [`EntryDecision::NonUtf8`]
+    Skipped(PathBuf, EntryDecision),
+}
+
+/// Like [`plan_entry_moves`], but the moves are calculated lazily while the directory is being
+/// listed, so neither the matching entries nor the moves are collected. Fragments of a matched
+/// entry are substituted right away without being copied. Entries which aren't matched are
+/// yielded too with the reason, see [`SourcePathPattern::entry_decisions`]. All the destinations
+/// are calculated with the time the iterator is created.
+///
+/// Note that the moves still have to be collected to be sorted or checked for collisions,
+/// see [`find_collisions`], as the `mmv` binary does. The iterator only saves memory for
+/// the entries which aren't moved.
+///
+/// # Examples
+/// ```
+/// use mmv_lib::{plan_moves_iter, DestinationPathTemplate, MatchOptions, PlannedEntry, SourcePathPattern};
+/// use std::{path::{Path, PathBuf}, str::FromStr};
+/// let source = SourcePathPattern::from_str("bin/*sh").unwrap();
+/// let template = DestinationPathTemplate::compile("shells/#1-shell", 1);
+/// let mut moves = plan_moves_iter(&source, &template, Path::new("/usr"), &MatchOptions::default());
+/// assert!(moves.any(|entry| {
+///     entry.unwrap() == PlannedEntry::Move("bin/bash".into(), "shells/ba-shell".into())
+/// }));
+/// ```
+pub fn plan_moves_iter<'a>(
+    source: &'a SourcePathPattern,
+    template: &'a DestinationPathTemplate,
+    working_directory: &Path,
+    options: &MatchOptions,
+) -> impl Iterator<Item = anyhow::Result<PlannedEntry>> + 'a {
+    let now = Local::now().naive_local();
+    source
+        .walk(
+            working_directory,
+            options,
+            move |source_filename, fragments| {
+                template.substitute_with_time(source_filename, fragments, &now)
+            },
+        )
+        .map(|entry| {
+            let (source_path, decision) = entry?;
+            Ok(match decision {
+                WalkDecision::Matched(destination_path) => {
+                    PlannedEntry::Move(source_path, destination_path)
+                }
+                WalkDecision::Skipped(EntryDecision::NonUtf8) => PlannedEntry::NonUtf8(source_path),
+                WalkDecision::Skipped(decision) => PlannedEntry::Skipped(source_path, decision),
+            })
+        })
+}
+
+/// Like [`plan_moves`], but the sources are taken from `paths` instead of listing
+/// a directory: each path whose filename matches the filename part of `source` is moved.
+/// The paths are kept as they are, so the directory part of `source` is ignored. If `source`
+/// is recursive, the fragment captured by `**` is the whole parent directory of the path.
+///
+/// # Examples
+/// ```
+/// use mmv_lib::{plan_moves_from_paths, DestinationPathTemplate, SourcePathPattern};
+/// use std::{path::PathBuf, str::FromStr};
+/// let moves = plan_moves_from_paths(
+///     &SourcePathPattern::from_str("*.log").unwrap(),
+///     &DestinationPathTemplate::compile("archive/#1.old", 1),
+///     ["logs/server.log", "notes.txt"].map(PathBuf::from),
+/// );
+/// assert_eq!(
+///     moves.moves,
+///     [(PathBuf::from("logs/server.log"), PathBuf::from("archive/server.old"))]
+/// );
+/// ```
+pub fn plan_moves_from_paths(
+    source: &SourcePathPattern,
+    template: &DestinationPathTemplate,
+    paths: impl IntoIterator<Item = PathBuf>,
+) -> PlannedMoves {
+    let mut planned_moves = PlannedMoves::default();
+    let now = Local::now().naive_local();
+    for source_path in paths {
+        let Some(source_filename) = source_path.file_name() else {
+            continue;
+        };
+        let Some(source_filename) = source_filename.to_str() else {
+            planned_moves.non_utf8.push(source_path);
+            continue;
+        };
+        if let Some(mut fragments_values) = source.match_filename(source_filename) {
+            if source.is_recursive() {
+                // There is no directory `**` is relative to, so the whole parent is captured
+                fragments_values.push(
+                    source_path
+                        .parent()
+                        .and_then(Path::to_str)
+                        .unwrap_or_default(),
+                );
+            }
+            let destination_path =
+                template.substitute_with_time(source_filename, &fragments_values, &now);
+            planned_moves.moves.push((source_path, destination_path));
+        }
+    }
+    planned_moves
+}
+
+/// Finds destinations which several sources are to be moved to. Returns such destinations
+/// in order of their first appearance in `moves`, each with all the sources aiming at it.
+/// Paths are compared by their components after the normalization done by [`Path`], e.g.
+/// `dir/./x` equals `dir/x`, but `dir/../x` doesn't equal `x`. The filesystem isn't accessed,
+/// so symlinks and case-insensitive names aren't taken into account.
+///
+/// # Examples
+/// ```
+/// use mmv_lib::find_collisions;
+/// use std::path::PathBuf;
+/// let moves = [("a.png", "image.png"), ("b.png", "b.jpg"), ("c.png", "image.png")]
+///     .map(|(source, destination)| (PathBuf::from(source), PathBuf::from(destination)));
+/// assert_eq!(
+///     find_collisions(&moves),
+///     [("image.png".into(), vec!["a.png".into(), "c.png".into()])]
+/// );
+/// ```
+pub fn find_collisions(moves: &[(PathBuf, PathBuf)]) -> Vec<(PathBuf, Vec<PathBuf>)> {
+    let mut sources_by_destination = HashMap::<&Path, Vec<&Path>>::with_capacity(moves.len());
+    let mut destinations = vec![];
+    for (source, destination) in moves {
+        let sources = sources_by_destination
+            .entry(destination)
+            .or_insert_with(|| {
+                destinations.push(destination.as_path());
+                vec![]
+            });
+        sources.push(source);
+    }
+    destinations
+        .into_iter()
+        .filter_map(|destination| {
+            let sources = &sources_by_destination[destination];
+            (sources.len() > 1).then(|| {
+                (
+                    destination.to_path_buf(),
+                    sources.iter().map(|source| source.to_path_buf()).collect(),
+                )
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test_plan_moves {
+    use std::{fs::File, path::PathBuf, str::FromStr};
+
+    use tempdir::TempDir;
+
+    use super::{
+        plan_entry_moves, plan_moves, plan_moves_from_paths, plan_moves_iter, PlannedEntry,
+    };
+    use crate::{DestinationPathTemplate, EntryDecision, MatchOptions, SourcePathPattern};
+
+    #[test]
+    fn it_works() {
+        let working_directory =
+            TempDir::new("mondas").expect("Failed to create a temporary directory");
+        std::fs::create_dir(working_directory.path().join("cybermen"))
+            .expect("Failed to create a directory");
+        std::fs::create_dir(working_directory.path().join("cybermen/mk-3"))
+            .expect("Failed to create a directory");
+        for filename in ["mk-1", "mk-2", "controller"] {
+            let _ = File::create(working_directory.path().join("cybermen").join(filename));
+        }
+
+        let source = SourcePathPattern::from_str("cybermen/mk-*").unwrap();
+        let template = DestinationPathTemplate::compile("upgraded/mark-#1", 1);
+        let mut moves =
+            plan_moves(&source, &template, working_directory.path()).expect("Shouldn't fail");
+        moves.sort();
+        assert_eq!(
+            moves,
+            [1, 2].map(|mark| (
+                PathBuf::from(format!("cybermen/mk-{mark}")),
+                PathBuf::from(format!("upgraded/mark-{mark}"))
+            ))
+        );
+        assert!(working_directory.path().join("cybermen/mk-1").exists());
+
+        let options = MatchOptions {
+            include_directories: true,
+            ..Default::default()
+        };
+        let mut moves = plan_entry_moves(&source, &template, working_directory.path(), &options)
+            .expect("Shouldn't fail")
+            .moves;
+        moves.sort();
+        assert_eq!(moves.len(), 3);
+        assert_eq!(moves[2], ("cybermen/mk-3".into(), "upgraded/mark-3".into()));
+    }
+
+    #[test]
+    fn iterator() -> anyhow::Result<()> {
+        let working_directory = TempDir::new("telos")?;
+        for filename in ["dalek-1", "dalek-2", "cyberman"] {
+            File::create(working_directory.path().join(filename))?;
+        }
+        std::fs::create_dir(working_directory.path().join("dalek-3"))?;
+        let source = SourcePathPattern::from_str("dalek-*").unwrap();
+        let template = DestinationPathTemplate::compile("#1.dalek", 1);
+        let mut entries = plan_moves_iter(
+            &source,
+            &template,
+            working_directory.path(),
+            &MatchOptions::default(),
+        )
+        .collect::<anyhow::Result<Vec<_>>>()?;
+        entries.sort_by_key(|entry| format!("{entry:?}"));
+        assert_eq!(
+            entries,
+            [
+                PlannedEntry::Move("dalek-1".into(), "1.dalek".into()),
+                PlannedEntry::Move("dalek-2".into(), "2.dalek".into()),
+                PlannedEntry::Skipped("cyberman".into(), EntryDecision::NotMatched),
+                PlannedEntry::Skipped("dalek-3".into(), EntryDecision::Directory),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn from_paths() {
+        let source = SourcePathPattern::from_str("ignored/mk-*").unwrap();
+        let template = DestinationPathTemplate::compile("upgraded/mark-#1", 1);
+        let moves = plan_moves_from_paths(
+            &source,
+            &template,
+            [
+                "cybermen/mk-1",
+                "mk-2",
+                "cybermen/controller",
+                "cybermen/",
+                "/",
+            ]
+            .map(PathBuf::from),
+        );
+        assert_eq!(
+            moves.moves,
+            [
+                ("cybermen/mk-1".into(), "upgraded/mark-1".into()),
+                ("mk-2".into(), "upgraded/mark-2".into())
+            ]
+        );
+        assert!(moves.non_utf8.is_empty());
+    }
+}
+
+#[cfg(test)]
+mod test_find_collisions {
+    use std::path::PathBuf;
+
+    use super::find_collisions;
+
+    fn moves(pairs: &[(&str, &str)]) -> Vec<(PathBuf, PathBuf)> {
+        pairs
+            .iter()
+            .map(|(source, destination)| (source.into(), destination.into()))
+            .collect()
+    }
+
+    #[test]
+    fn no_collisions() {
+        assert!(find_collisions(&[]).is_empty());
+        assert!(find_collisions(&moves(&[("a", "b"), ("b", "c"), ("c", "a")])).is_empty());
+        assert!(find_collisions(&moves(&[("a", "dir/x"), ("b", "other/x")])).is_empty());
+    }
+
+    #[test]
+    fn collisions() {
+        assert_eq!(
+            find_collisions(&moves(&[
+                ("z", "second"),
+                ("a", "first"),
+                ("b", "unique"),
+                ("c", "first"),
+                ("d", "second"),
+                ("e", "first"),
+            ])),
+            vec![
+                ("second".into(), vec!["z".into(), "d".into()]),
+                ("first".into(), vec!["a".into(), "c".into(), "e".into()]),
+            ]
+        );
+        assert_eq!(
+            find_collisions(&moves(&[("a", "dir/./x"), ("b", "dir/x")])),
+            vec![("dir/./x".into(), vec!["a".into(), "b".into()])]
+        );
+    }
+}
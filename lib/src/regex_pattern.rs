@@ -0,0 +1,120 @@
+use std::fmt::Display;
+
+use regex::Regex;
+
+/// A compiled regular expression matching whole strings. Capture groups play the role of
+/// wildcards: `N`th group corresponds to `N`th fragment.
+#[derive(Debug, Clone)]
+pub struct RegexPattern {
+    /// The pattern as it was written by user
+    pattern: String,
+    /// The pattern anchored at both ends
+    regex: Regex,
+}
+
+impl RegexPattern {
+    /// Compiles the `pattern`. It must match the whole string, i.e. it's implicitly anchored.
+    ///
+    /// # Examples
+    /// ```
+    /// use mmv_lib::RegexPattern;
+    /// assert!(RegexPattern::new(r"(\d+)-(.*)\.log").is_ok());
+    /// assert!(RegexPattern::new(r"(\d+").is_err());
+    /// ```
+    pub fn new(pattern: &str) -> Result<Self, regex::Error> {
+        Ok(Self {
+            pattern: pattern.to_string(),
+            regex: Regex::new(&format!("^(?:{pattern})$"))?,
+        })
+    }
+
+    /// Returns the number of capture groups in the pattern
+    ///
+    /// # Examples
+    /// ```
+    /// use mmv_lib::RegexPattern;
+    /// let pattern = RegexPattern::new(r"(\d+)-(?:.*)\.(log|txt)").unwrap();
+    /// assert_eq!(pattern.captures_number(), 2);
+    /// ```
+    pub fn captures_number(&self) -> usize {
+        self.regex.captures_len() - 1
+    }
+
+    /// Returns the pattern as it was written
+    pub(crate) fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    /// Matches the `string` to the pattern.
+    /// It the string matches, returns `Vec<&str>` of `string`'s substrings captured by groups.
+    /// A group which didn't participate in the match captures an empty string.
+    /// Otherwise returns `None`.
+    ///
+    /// # Examples
+    /// ```
+    /// use mmv_lib::RegexPattern;
+    /// let pattern = RegexPattern::new(r"(\d+)-(.*)\.log").unwrap();
+    /// assert_eq!(
+    ///     pattern.match_string("42-answer.log"),
+    ///     vec!["42", "answer"].into(),
+    /// );
+    /// assert!(pattern.match_string("answer-42.log").is_none());
+    /// ```
+    pub fn match_string<'a>(&self, string: &'a str) -> Option<Vec<&'a str>> {
+        let captures = self.regex.captures(string)?;
+        Some(
+            captures
+                .iter()
+                .skip(1)
+                .map(|group| group.map_or("", |group| group.as_str()))
+                .collect(),
+        )
+    }
+}
+
+impl PartialEq for RegexPattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.pattern == other.pattern
+    }
+}
+
+impl Eq for RegexPattern {}
+
+impl Display for RegexPattern {
+    fn fmt(&self, format: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(format, "{}", self.pattern)
+    }
+}
+
+#[cfg(test)]
+mod test_regex_pattern {
+    use super::RegexPattern;
+
+    #[test]
+    fn match_string() {
+        let pattern = RegexPattern::new(r"(\d+)-(.*)\.log").unwrap();
+        assert_eq!(pattern.captures_number(), 2);
+        assert_eq!(
+            pattern.match_string("2023-server.log"),
+            Some(vec!["2023", "server"])
+        );
+        assert_eq!(pattern.match_string("1-.log"), Some(vec!["1", ""]));
+        assert_eq!(pattern.match_string("2023-server.log.gz"), None);
+        assert_eq!(pattern.match_string("old-2023-server.log"), None);
+        assert_eq!(pattern.match_string("-server.log"), None);
+    }
+
+    #[test]
+    fn alternation_is_anchored() {
+        let pattern = RegexPattern::new(r"a|b").unwrap();
+        assert_eq!(pattern.captures_number(), 0);
+        assert_eq!(pattern.match_string("a"), Some(vec![]));
+        assert_eq!(pattern.match_string("ab"), None);
+    }
+
+    #[test]
+    fn optional_group() {
+        let pattern = RegexPattern::new(r"(\w+)(_draft)?\.txt").unwrap();
+        assert_eq!(pattern.match_string("essay.txt"), Some(vec!["essay", ""]));
+    }
+}
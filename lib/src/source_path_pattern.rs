@@ -1,23 +1,395 @@
 use std::{
+    ffi::OsString,
     fmt::Display,
+    fs::{DirEntry, ReadDir},
     path::{Path, PathBuf},
     str::FromStr,
+    sync::Arc,
 };
 
 use anyhow::Context;
 
-use crate::glob_star_pattern::GlobStarPattern;
+use crate::{
+    brace_expansion::expand_braces,
+    glob_star_pattern::{GlobStarPattern, MatchingMode},
+    matcher::Matcher,
+    regex_pattern::RegexPattern,
+};
+
+/// Pattern which filenames are matched against
+#[derive(Debug, Clone)]
+enum FilenamePattern {
+    Glob(GlobStarPattern),
+    /// Brace expansion of `pattern`: a filename is matched by the first matching alternative.
+    /// All the alternatives have the same number of wildcards.
+    Alternatives {
+        pattern: String,
+        alternatives: Vec<GlobStarPattern>,
+    },
+    Regex(RegexPattern),
+    /// Pattern of a path relative to the directory, see [`SourcePathPattern::from_path_glob`]
+    Path(GlobStarPattern),
+    /// Matcher given by the library user, see [`SourcePathPattern::from_matcher`]
+    Custom(Arc<dyn Matcher>),
+}
+
+impl FilenamePattern {
+    /// Compiles the `pattern` expanding braces, see [`expand_braces`]
+    fn glob(pattern: &str) -> Result<Self, &'static str> {
+        let mut alternatives = expand_braces(pattern)
+            .iter()
+            .map(|alternative| GlobStarPattern::from(alternative.as_str()))
+            .collect::<Vec<_>>();
+        if alternatives.len() == 1 {
+            return Ok(Self::Glob(alternatives.pop().unwrap()));
+        }
+        let wildcards_number = alternatives[0].wildcards_number();
+        if alternatives
+            .iter()
+            .any(|alternative| alternative.wildcards_number() != wildcards_number)
+        {
+            return Err("All alternatives in braces must have the same number of '*'-wildcards");
+        }
+        Ok(Self::Alternatives {
+            pattern: pattern.to_string(),
+            alternatives,
+        })
+    }
+
+    /// Depth of the matched paths relative to the directory, i.e. number of slashes in them
+    fn depth(&self) -> usize {
+        match self {
+            Self::Path(pattern) => pattern.chars().filter(|&char| char == '/').count(),
+            _ => 0,
+        }
+    }
+
+    /// Matches the `path` like [`Matcher::match_string`]. Unless `include_hidden` is set, hidden
+    /// entries are matched only by patterns which explicitly refer to them: a path pattern must
+    /// begin with a dot in each component where the path does, other patterns must begin with
+    /// a dot if the filename does, see [`Matcher::matches_hidden`]. Brace alternatives are
+    /// checked separately, so `{.c*,*}` matches `.cfg`, but not `.bashrc`.
+    fn match_entry<'a>(
+        &self,
+        path: &'a str,
+        include_hidden: bool,
+    ) -> Result<Vec<&'a str>, EntryDecision> {
+        let allows_hidden = |pattern: &dyn Matcher| {
+            include_hidden || !path.starts_with('.') || pattern.matches_hidden()
+        };
+        let allowed = match self {
+            Self::Glob(pattern) => allows_hidden(pattern),
+            Self::Alternatives { alternatives, .. } => {
+                let mut allowed_alternatives = alternatives
+                    .iter()
+                    .filter(|alternative| allows_hidden(*alternative))
+                    .peekable();
+                if allowed_alternatives.peek().is_none() {
+                    return Err(EntryDecision::Hidden);
+                }
+                return allowed_alternatives
+                    .find_map(|alternative| alternative.match_string(path))
+                    .ok_or(EntryDecision::NotMatched);
+            }
+            Self::Regex(pattern) => allows_hidden(pattern),
+            Self::Path(pattern) => include_hidden || path_allows_hidden(pattern, path),
+            Self::Custom(matcher) => allows_hidden(matcher.as_ref()),
+        };
+        if !allowed {
+            return Err(EntryDecision::Hidden);
+        }
+        self.match_string(path).ok_or(EntryDecision::NotMatched)
+    }
+}
+
+/// Whether each component of the `path` which begins with a dot is matched by a component of
+/// the path `pattern` which begins with a dot too
+fn path_allows_hidden(pattern: &GlobStarPattern, path: &str) -> bool {
+    let mut pattern_chars = pattern.chars();
+    path.split('/').all(|component| {
+        let first_char = pattern_chars.next();
+        if first_char != Some('/') {
+            // Skips the rest of the pattern component
+            pattern_chars.find(|&char| char == '/');
+        }
+        first_char.is_none() || !component.starts_with('.') || first_char == Some('.')
+    })
+}
+
+impl Matcher for FilenamePattern {
+    fn match_string<'a>(&self, string: &'a str) -> Option<Vec<&'a str>> {
+        match self {
+            Self::Glob(pattern) => pattern.match_string(string),
+            Self::Alternatives { alternatives, .. } => alternatives
+                .iter()
+                .find_map(|alternative| alternative.match_string(string)),
+            Self::Regex(pattern) => pattern.match_string(string),
+            Self::Path(pattern) => pattern.match_path(string),
+            Self::Custom(matcher) => matcher.match_string(string),
+        }
+    }
+
+    fn wildcards_number(&self) -> usize {
+        match self {
+            Self::Glob(pattern) => pattern.wildcards_number(),
+            Self::Alternatives { alternatives, .. } => alternatives[0].wildcards_number(),
+            Self::Regex(pattern) => pattern.captures_number(),
+            Self::Path(pattern) => pattern.wildcards_number(),
+            Self::Custom(matcher) => matcher.wildcards_number(),
+        }
+    }
+}
+
+impl PartialEq for FilenamePattern {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Glob(left), Self::Glob(right)) | (Self::Path(left), Self::Path(right)) => {
+                left == right
+            }
+            (
+                Self::Alternatives {
+                    alternatives: left, ..
+                },
+                Self::Alternatives {
+                    alternatives: right,
+                    ..
+                },
+            ) => left == right,
+            (Self::Regex(left), Self::Regex(right)) => left == right,
+            // Arbitrary matchers cannot be compared, so only the same instance is equal
+            (Self::Custom(left), Self::Custom(right)) => Arc::ptr_eq(left, right),
+            _ => false,
+        }
+    }
+}
+
+impl Eq for FilenamePattern {}
+
+impl From<GlobStarPattern> for FilenamePattern {
+    fn from(pattern: GlobStarPattern) -> Self {
+        Self::Glob(pattern)
+    }
+}
+
+impl Display for FilenamePattern {
+    fn fmt(&self, format: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Glob(pattern) => write!(format, "{pattern}"),
+            Self::Alternatives { pattern, .. } => write!(format, "{pattern}"),
+            Self::Regex(pattern) => write!(format, "{pattern}"),
+            Self::Path(pattern) => write!(format, "{pattern}"),
+            // Custom matchers aren't required to be displayable
+            Self::Custom(matcher) => write!(format, "{matcher:?}"),
+        }
+    }
+}
+
+/// Options controlling which directory entries are matched
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MatchOptions {
+    /// Match directories as well as files. `**` doesn't descend into a matched directory,
+    /// since it's moved with all its content.
+    pub include_directories: bool,
+    /// Classify symlinks by their targets. Otherwise a symlink is matched as a file regardless of
+    /// its target, and dangling symlinks are matched too.
+    pub follow_symlinks: bool,
+    /// Match hidden entries, i.e. ones whose names begin with `.`, and look for files in hidden
+    /// subdirectories of `**`. Otherwise hidden entries are matched only if the filename pattern
+    /// itself begins with `.`, e.g. `*` doesn't match `.config` while `.*` does.
+    pub include_hidden: bool,
+    /// Maximal depth of subdirectories `**` descends into, counted from the directory before
+    /// `**`: `0` means the directory itself, `1` means its subdirectories, and so on.
+    /// Unlimited if `None`, doesn't affect non-recursive patterns.
+    pub max_depth: Option<usize>,
+}
+
+/// Entries found by [`SourcePathPattern::matching_entries`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MatchingEntries {
+    /// Paths of the matched entries with fragments matched by wildcards
+    pub matched: Vec<(PathBuf, Vec<String>)>,
+    /// Paths of the entries which weren't matched against the pattern because their names
+    /// aren't valid UTF-8
+    pub non_utf8: Vec<PathBuf>,
+}
 
 /// Source path pattern. Acts like [glob](https://en.wikipedia.org/wiki/Glob_(programming))
 /// but only single star (`*`) wildcard in filenames is supported.
+/// A leading `~` in the directory is expanded to the home directory, `~user` is not supported.
+///
+/// The last directory component can be `**`, which matches the directory itself and all its
+/// subdirectories recursively: `src/**/*.rs` matches `src/main.rs` and `src/lib/mod.rs`.
+/// The path of the subdirectory relative to the directory before `**` is captured as
+/// an additional fragment which goes after the filename fragments, e.g. `lib/mod.rs` gives `mod`
+/// and `lib`. The captured path is empty for files in the directory itself.
+///
+/// Like in shells, hidden entries, i.e. ones whose names begin with `.`, aren't matched unless
+/// the filename pattern begins with `.` too, see [`MatchOptions::include_hidden`].
+///
+/// Alternatively, filenames can be matched by a regular expression, see
+/// [`from_regex`][SourcePathPattern::from_regex], or by any [`Matcher`], see
+/// [`from_matcher`][SourcePathPattern::from_matcher].
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct SourcePathPattern {
     directory: PathBuf,
-    filename_pattern: GlobStarPattern,
+    /// Whether the directory is followed by `**`
+    recursive: bool,
+    filename_pattern: FilenamePattern,
+}
+
+/// Splits `path_pattern` into directory and filename parts at the last slash
+fn split_directory(path_pattern: &str) -> (&str, &str) {
+    path_pattern.split_at(
+        path_pattern
+            .rfind('/')
+            .map_or(0, |slash_position| slash_position + 1),
+    )
+}
+
+/// Splits `directory` into the directory itself and a flag whether it ends with `**` component
+fn split_recursive(directory: &str) -> (&str, bool) {
+    match directory.strip_suffix("**/") {
+        Some(parent) if parent.is_empty() || parent.ends_with('/') => (parent, true),
+        _ => (directory, false),
+    }
+}
+
+/// Replaces a leading `~` in `directory` by `home`, which is normally taken from `HOME`
+/// environment variable, e.g. `~/Downloads/` becomes `/home/user/Downloads/`. `~user` is not
+/// supported and left as is, as well as `~` if `home` is `None`.
+pub(crate) fn expand_tilde(directory: &str, home: Option<OsString>) -> PathBuf {
+    match (directory.strip_prefix('~'), home) {
+        (Some(rest), Some(mut home)) if rest.is_empty() || rest.starts_with('/') => {
+            home.push(rest);
+            PathBuf::from(home)
+        }
+        _ => PathBuf::from(directory),
+    }
 }
 
 impl SourcePathPattern {
-    /// Number of '*'-wildcards in the filename pattern
+    /// Creates a pattern which matches filenames by a regular expression, see [`RegexPattern`].
+    /// Everything before the last slash is a literal directory path, so the expression itself
+    /// must not contain slashes. Capture groups play the role of wildcards.
+    ///
+    /// # Examples
+    /// ```
+    /// use mmv_lib::SourcePathPattern;
+    /// let pattern = SourcePathPattern::from_regex(r"logs/(\d+)-(.*)\.log").unwrap();
+    /// assert_eq!(pattern.wildcards_number(), 2);
+    /// assert!(SourcePathPattern::from_regex(r"logs/(\d+").is_err());
+    /// ```
+    pub fn from_regex(string: &str) -> Result<Self, regex::Error> {
+        let (directory_str, filename_pattern_str) = split_directory(string);
+        let (directory_str, recursive) = split_recursive(directory_str);
+        Ok(Self {
+            directory: expand_tilde(directory_str, std::env::var_os("HOME")),
+            recursive,
+            filename_pattern: FilenamePattern::Regex(RegexPattern::new(filename_pattern_str)?),
+        })
+    }
+
+    /// Creates a pattern which matches filenames in the `directory` by the `matcher`.
+    /// Like in other patterns, the `directory` can end with `**` and begin with `~`.
+    ///
+    /// # Examples
+    /// ```
+    /// use mmv_lib::{GlobStarPattern, SourcePathPattern};
+    /// let pattern = SourcePathPattern::from_matcher("logs/**", GlobStarPattern::from("*.log"));
+    /// assert_eq!(pattern.wildcards_number(), 2);
+    /// assert!(pattern.to_string().starts_with("logs/**/"));
+    /// ```
+    pub fn from_matcher(directory: &str, matcher: impl Matcher + 'static) -> Self {
+        let directory = if directory.is_empty() || directory.ends_with('/') {
+            directory.to_string()
+        } else {
+            format!("{directory}/")
+        };
+        let (directory_str, recursive) = split_recursive(&directory);
+        Self {
+            directory: expand_tilde(directory_str, std::env::var_os("HOME")),
+            recursive,
+            filename_pattern: FilenamePattern::Custom(Arc::new(matcher)),
+        }
+    }
+
+    /// Creates a pattern which matches whole paths relative to the directory instead of filenames,
+    /// so wildcards can appear in directories as well, e.g. `src/a*/*.txt` matches
+    /// `src/abc/notes.txt`. The directory is the longest prefix of the pattern without wildcards,
+    /// and the rest is matched component by component, i.e. `*` never matches `/`.
+    /// Braces aren't expanded, and `**` isn't supported.
+    ///
+    /// # Examples
+    /// ```
+    /// use mmv_lib::SourcePathPattern;
+    /// let pattern = SourcePathPattern::from_path_glob("src/a*/*.txt").unwrap();
+    /// assert_eq!(pattern.wildcards_number(), 2);
+    /// assert_eq!(pattern.match_filename("abc/notes.txt"), vec!["bc", "notes"].into());
+    /// assert!(pattern.match_filename("notes.txt").is_none());
+    /// assert!(SourcePathPattern::from_path_glob("src/**/*.txt").is_err());
+    /// ```
+    pub fn from_path_glob(string: &str) -> Result<Self, &'static str> {
+        let directory_length = match string.find('*') {
+            Some(wildcard_position) => string[..wildcard_position]
+                .rfind('/')
+                .map_or(0, |slash_position| slash_position + 1),
+            None => split_directory(string).0.len(),
+        };
+        let (directory_str, path_pattern_str) = string.split_at(directory_length);
+        if path_pattern_str
+            .split('/')
+            .any(|component| component == "**")
+        {
+            return Err("'**' cannot be used when whole paths are matched");
+        }
+        let filename_pattern = if path_pattern_str.contains('/') {
+            FilenamePattern::Path(GlobStarPattern::from(path_pattern_str))
+        } else {
+            FilenamePattern::glob(path_pattern_str)?
+        };
+        Ok(Self {
+            directory: expand_tilde(directory_str, std::env::var_os("HOME")),
+            recursive: false,
+            filename_pattern,
+        })
+    }
+
+    /// Returns the same pattern resolving ambiguous matches in the given `mode`,
+    /// see [`MatchingMode`]. Regular expressions are not affected: their greediness is defined
+    /// by the expression itself.
+    ///
+    /// # Examples
+    /// ```
+    /// use mmv_lib::{MatchingMode, SourcePathPattern};
+    /// use std::str::FromStr;
+    /// let pattern = SourcePathPattern::from_str("src/*.rs*.rs")
+    ///     .unwrap()
+    ///     .with_matching_mode(MatchingMode::Greedy);
+    /// assert_eq!(pattern.to_string(), "src/*.rs*.rs");
+    /// ```
+    pub fn with_matching_mode(self, mode: MatchingMode) -> Self {
+        let filename_pattern = match self.filename_pattern {
+            FilenamePattern::Glob(pattern) => FilenamePattern::Glob(pattern.with_mode(mode)),
+            FilenamePattern::Path(pattern) => FilenamePattern::Path(pattern.with_mode(mode)),
+            FilenamePattern::Alternatives {
+                pattern,
+                alternatives,
+            } => FilenamePattern::Alternatives {
+                pattern,
+                alternatives: alternatives
+                    .into_iter()
+                    .map(|alternative| alternative.with_mode(mode))
+                    .collect(),
+            },
+            other_pattern => other_pattern,
+        };
+        Self {
+            filename_pattern,
+            ..self
+        }
+    }
+
+    /// Number of '*'-wildcards in the filename pattern plus one if the directory ends with `**`
     ///
     /// # Examples
     /// ```
@@ -25,13 +397,53 @@ impl SourcePathPattern {
     /// use std::str::FromStr;
     /// let pattern = SourcePathPattern::from_str("rust/version-*.*").unwrap();
     /// assert_eq!(pattern.wildcards_number(), 2);
+    /// let recursive_pattern = SourcePathPattern::from_str("rust/**/version-*.*").unwrap();
+    /// assert_eq!(recursive_pattern.wildcards_number(), 3);
     /// ```
     pub fn wildcards_number(&self) -> usize {
-        self.filename_pattern.wildcards_number()
+        self.filename_pattern.wildcards_number() + self.recursive as usize
+    }
+
+    /// Returns `true` if the directory ends with `**`, so the last fragment is
+    /// a captured subdirectory path
+    pub fn is_recursive(&self) -> bool {
+        self.recursive
+    }
+
+    /// Returns the directory part of the pattern, without `**`
+    ///
+    /// # Examples
+    /// ```
+    /// use mmv_lib::SourcePathPattern;
+    /// use std::{path::Path, str::FromStr};
+    /// let pattern = SourcePathPattern::from_str("logs/**/*.log").unwrap();
+    /// assert_eq!(pattern.directory(), Path::new("logs/"));
+    /// ```
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// Matches the `filename` to the filename part of the pattern, ignoring the directory part.
+    /// Returns fragments matched by wildcards if the filename matches. The fragment captured
+    /// by `**` isn't included. A pattern created by
+    /// [`from_path_glob`][SourcePathPattern::from_path_glob] matches a path relative to
+    /// the directory instead.
+    ///
+    /// # Examples
+    /// ```
+    /// use mmv_lib::SourcePathPattern;
+    /// use std::str::FromStr;
+    /// let pattern = SourcePathPattern::from_str("logs/*.log").unwrap();
+    /// assert_eq!(pattern.match_filename("server.log"), vec!["server"].into());
+    /// assert!(pattern.match_filename("server.txt").is_none());
+    /// ```
+    pub fn match_filename<'a>(&self, filename: &'a str) -> Option<Vec<&'a str>> {
+        self.filename_pattern.match_string(filename)
     }
 
     /// Returns `Vec` of file paths matched by the pattern with the corresponding matching
     /// information, i.e. data returned by `GlobStarPattern::match_string`.
+    /// Hidden files are matched only if the pattern begins with `.`.
     ///
     /// # Exmaples
     /// ```
@@ -50,37 +462,282 @@ impl SourcePathPattern {
         &self,
         working_directory: &Path,
     ) -> anyhow::Result<Vec<(PathBuf, Vec<String>)>> {
-        let mut result = vec![];
+        self.matching_files_iter(working_directory).collect()
+    }
+
+    /// Like [`matching_files`][SourcePathPattern::matching_files], but lists the directory
+    /// lazily, yielding the matched files one by one. If the directory cannot be read, the only
+    /// item is the error.
+    ///
+    /// # Examples
+    /// ```
+    /// use mmv_lib::SourcePathPattern;
+    /// use std::{str::FromStr, path::{Path, PathBuf}};
+    /// let pattern = SourcePathPattern::from_str("bin/*sh").unwrap();
+    /// let bash = pattern
+    ///     .matching_files_iter(&Path::new("/usr"))
+    ///     .map(Result::unwrap)
+    ///     .find(|(path, _)| path == &PathBuf::from("bin/bash"));
+    /// assert_eq!(bash.unwrap().1, vec!["ba".to_string()]);
+    /// ```
+    pub fn matching_files_iter<'a>(
+        &'a self,
+        working_directory: &Path,
+    ) -> impl Iterator<Item = anyhow::Result<(PathBuf, Vec<String>)>> + 'a {
+        self.entry_decisions(working_directory, &MatchOptions::default())
+            .filter_map(|entry| match entry {
+                Ok((path, EntryDecision::Matched(match_info))) => Some(Ok((path, match_info))),
+                Ok(_) => None,
+                Err(error) => Some(Err(error)),
+            })
+    }
+
+    /// Like [`matching_files`][SourcePathPattern::matching_files], but the set of matched
+    /// entries is controlled by `options`, see [`MatchOptions`].
+    ///
+    /// Fragments must be valid UTF-8 to be substituted into a destination, so entries whose
+    /// names aren't valid UTF-8 are never matched. Instead of being dropped silently they are
+    /// listed in [`MatchingEntries::non_utf8`].
+    ///
+    /// # Examples
+    /// ```
+    /// use mmv_lib::{MatchOptions, SourcePathPattern};
+    /// use std::{str::FromStr, path::{Path, PathBuf}};
+    /// let pattern = SourcePathPattern::from_str("*bin").unwrap();
+    /// let options = MatchOptions {
+    ///     include_directories: true,
+    ///     ..Default::default()
+    /// };
+    /// let directories = pattern.matching_entries(&Path::new("/usr"), &options).unwrap();
+    /// assert!(directories.matched.contains(&(PathBuf::from("bin"), vec!["".to_string()])));
+    /// assert!(pattern.matching_files(&Path::new("/usr")).unwrap().is_empty());
+    /// ```
+    pub fn matching_entries(
+        &self,
+        working_directory: &Path,
+        options: &MatchOptions,
+    ) -> anyhow::Result<MatchingEntries> {
+        let mut result = MatchingEntries::default();
+        for entry in self.entry_decisions(working_directory, options) {
+            match entry? {
+                (path, EntryDecision::Matched(match_info)) => {
+                    result.matched.push((path, match_info))
+                }
+                (path, EntryDecision::NonUtf8) => result.non_utf8.push(path),
+                _ => {}
+            }
+        }
+        Ok(result)
+    }
+
+    /// Lazily lists all entries of the pattern's directory (and its subdirectories if
+    /// the pattern is recursive), each with the decision whether it's matched and why not.
+    /// Useful for finding out why a pattern doesn't match some file.
+    ///
+    /// # Examples
+    /// ```
+    /// use mmv_lib::{EntryDecision, MatchOptions, SourcePathPattern};
+    /// use std::{str::FromStr, path::{Path, PathBuf}};
+    /// let pattern = SourcePathPattern::from_str("bin/*sh").unwrap();
+    /// let decisions = pattern
+    ///     .entry_decisions(&Path::new("/usr"), &MatchOptions::default())
+    ///     .collect::<anyhow::Result<Vec<_>>>()
+    ///     .unwrap();
+    /// assert!(decisions.contains(&(PathBuf::from("bin/ls"), EntryDecision::NotMatched)));
+    /// ```
+    pub fn entry_decisions<'a>(
+        &'a self,
+        working_directory: &Path,
+        options: &MatchOptions,
+    ) -> impl Iterator<Item = anyhow::Result<(PathBuf, EntryDecision)>> + 'a {
+        self.walk(working_directory, options, |_, fragments| {
+            fragments
+                .iter()
+                .map(|fragment| fragment.to_string())
+                .collect()
+        })
+        .map(|entry| {
+            entry.map(|(path, decision)| match decision {
+                WalkDecision::Matched(fragments) => (path, EntryDecision::Matched(fragments)),
+                WalkDecision::Skipped(decision) => (path, decision),
+            })
+        })
+    }
+
+    /// Like [`entry_decisions`][SourcePathPattern::entry_decisions], but a matched entry is
+    /// passed to `on_match` with its filename and borrowed fragments, including the one captured
+    /// by `**`, and the result is yielded instead of owned fragments.
+    pub(crate) fn walk<'a, T>(
+        &'a self,
+        working_directory: &Path,
+        options: &MatchOptions,
+        mut on_match: impl FnMut(&str, &[&str]) -> T + 'a,
+    ) -> impl Iterator<Item = anyhow::Result<(PathBuf, WalkDecision<T>)>> + 'a {
+        let options = *options;
         let directory_path = working_directory.join(self.directory.clone());
-        for dir_entry in std::fs::read_dir(directory_path.clone()).context(format!(
-            "Failed to read {:#?} directory content",
-            directory_path
-        ))? {
-            let entry_unwrapped = dir_entry.context("Failed to read entry")?;
-            let metadata = entry_unwrapped.metadata().context(format!(
-                "Failed to get metadata for {:#?}",
-                entry_unwrapped.path()
-            ))?;
-            if metadata.is_file() {
-                if let Some(filaname) = entry_unwrapped.file_name().to_str() {
-                    let match_result = self.filename_pattern.match_string(filaname);
-                    if let Some(match_info) = match_result {
-                        result.push((
-                            self.directory.join(entry_unwrapped.file_name()),
-                            match_info.into_iter().map(str::to_string).collect(),
-                        ));
+        // Subdirectories to be listed, relative to `directory_path`
+        let mut pending_subdirectories = vec![String::new()];
+        let mut current_subdirectory: Option<(String, ReadDir)> = None;
+        std::iter::from_fn(move || loop {
+            let Some((subdirectory, dir_entries)) = &mut current_subdirectory else {
+                let subdirectory = pending_subdirectories.pop()?;
+                let subdirectory_path = directory_path.join(&subdirectory);
+                match std::fs::read_dir(&subdirectory_path).context(format!(
+                    "Failed to read {:#?} directory content",
+                    subdirectory_path
+                )) {
+                    Ok(dir_entries) => current_subdirectory = Some((subdirectory, dir_entries)),
+                    Err(error) => return Some(Err(error)),
+                }
+                continue;
+            };
+            let Some(dir_entry) = dir_entries.next() else {
+                current_subdirectory = None;
+                continue;
+            };
+            return Some(self.decide(
+                dir_entry,
+                subdirectory,
+                &options,
+                &mut pending_subdirectories,
+                &mut on_match,
+            ));
+        })
+    }
+
+    /// Decides whether an entry of `subdirectory` is matched. Subdirectories to descend into are
+    /// appended to `pending_subdirectories` if the pattern is recursive, except matched ones.
+    fn decide<T>(
+        &self,
+        dir_entry: std::io::Result<DirEntry>,
+        subdirectory: &str,
+        options: &MatchOptions,
+        pending_subdirectories: &mut Vec<String>,
+        on_match: &mut impl FnMut(&str, &[&str]) -> T,
+    ) -> anyhow::Result<(PathBuf, WalkDecision<T>)> {
+        let entry_unwrapped = dir_entry.context("Failed to read entry")?;
+        let file_type = entry_unwrapped.file_type().context(format!(
+            "Failed to get file type of {:#?}",
+            entry_unwrapped.path()
+        ))?;
+        let entry_path = if subdirectory.is_empty() {
+            self.directory.join(entry_unwrapped.file_name())
+        } else {
+            self.directory
+                .join(subdirectory)
+                .join(entry_unwrapped.file_name())
+        };
+        let filename = entry_unwrapped.file_name().to_str().map(str::to_string);
+        let is_hidden = entry_unwrapped
+            .file_name()
+            .as_encoded_bytes()
+            .starts_with(b".");
+        let depth = subdirectory
+            .split('/')
+            .filter(|part| !part.is_empty())
+            .count();
+        let descends = if self.recursive {
+            (options.include_hidden || !is_hidden)
+                && options.max_depth.is_none_or(|max_depth| depth < max_depth)
+        } else {
+            // Hidden directories are checked against the path pattern when entries are matched
+            depth < self.filename_pattern.depth()
+        };
+        // Symlinks to directories aren't followed to avoid cycles
+        let subdirectory_to_descend = match &filename {
+            Some(filename) if file_type.is_dir() && descends => Some(if subdirectory.is_empty() {
+                filename.clone()
+            } else {
+                format!("{subdirectory}/{filename}")
+            }),
+            None if file_type.is_dir() && descends => {
+                return Ok((entry_path, WalkDecision::Skipped(EntryDecision::NonUtf8)))
+            }
+            _ => None,
+        };
+        let decision = 'decision: {
+            let file_type = if file_type.is_symlink() && options.follow_symlinks {
+                // A dangling symlink has no target to classify, so it's skipped
+                match std::fs::metadata(entry_unwrapped.path()) {
+                    Ok(metadata) => metadata.file_type(),
+                    Err(_) => {
+                        break 'decision WalkDecision::Skipped(EntryDecision::DanglingSymlink)
+                    }
+                }
+            } else {
+                file_type
+            };
+            if file_type.is_dir() && !options.include_directories {
+                break 'decision WalkDecision::Skipped(EntryDecision::Directory);
+            }
+            if !(file_type.is_file() || file_type.is_symlink() || file_type.is_dir()) {
+                break 'decision WalkDecision::Skipped(EntryDecision::SpecialFile);
+            }
+            let Some(filaname) = filename else {
+                break 'decision WalkDecision::Skipped(EntryDecision::NonUtf8);
+            };
+            let matched_path = match &self.filename_pattern {
+                FilenamePattern::Path(_) if !subdirectory.is_empty() => {
+                    format!("{subdirectory}/{filaname}")
+                }
+                _ => filaname,
+            };
+            // Filenames can't contain slashes, so the filename is the last component of the path
+            let entry_filename = matched_path.rsplit('/').next().unwrap_or_default();
+            match self
+                .filename_pattern
+                .match_entry(&matched_path, options.include_hidden)
+            {
+                Ok(mut fragments) => {
+                    if self.recursive {
+                        fragments.push(subdirectory);
                     }
+                    WalkDecision::Matched(on_match(entry_filename, &fragments))
                 }
+                Err(decision) => WalkDecision::Skipped(decision),
             }
+        };
+        // A matched directory is moved as a whole, so entries inside it aren't matched separately
+        if let (Some(subdirectory), WalkDecision::Skipped(_)) = (subdirectory_to_descend, &decision)
+        {
+            pending_subdirectories.push(subdirectory);
         }
-        Ok(result)
+        Ok((entry_path, decision))
     }
 }
 
+/// Decision made about a directory entry by [`SourcePathPattern::walk`]
+pub(crate) enum WalkDecision<T> {
+    /// The entry is matched, the result of `on_match` is attached
+    Matched(T),
+    /// The entry isn't matched, see [`EntryDecision`] for the reasons
+    Skipped(EntryDecision),
+}
+
+/// Decision made about a directory entry by [`SourcePathPattern::entry_decisions`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EntryDecision {
+    /// The entry is matched, fragments matched by wildcards are attached
+    Matched(Vec<String>),
+    /// The name doesn't match the pattern
+    NotMatched,
+    /// The entry is a directory, but directories aren't matched
+    Directory,
+    /// The entry is a dangling symlink, which cannot be classified when symlinks are followed
+    DanglingSymlink,
+    /// The entry is neither a file, nor a directory, nor a symlink, e.g. a socket
+    SpecialFile,
+    /// The name begins with `.`, but hidden entries aren't matched,
+    /// see [`MatchOptions::include_hidden`]
+    Hidden,
+    /// The name isn't valid UTF-8, so it cannot be matched
+    NonUtf8,
+}
+
 #[cfg(test)]
 mod test_getting_matching_files {
-    use super::SourcePathPattern;
-    use std::{fs::File, str::FromStr};
+    use super::{MatchOptions, SourcePathPattern};
+    use std::{cell::Cell, fs::File, path::PathBuf, str::FromStr};
     use tempdir::TempDir;
 
     #[test]
@@ -126,34 +783,666 @@ mod test_getting_matching_files {
         assert_eq!(matched_files, expected_files);
         Ok(())
     }
+
+    #[test]
+    fn iterator() -> anyhow::Result<()> {
+        let working_directory =
+            TempDir::new("shada").expect("Failed to create a temporary directory");
+        for index in 0..50 {
+            let _ = File::create(working_directory.path().join(format!("record-{index}.txt")));
+        }
+        let _ = File::create(working_directory.path().join("record.md"));
+        let pattern = SourcePathPattern::from_str("record-*.txt").unwrap();
+
+        let mut lazily_matched = pattern
+            .matching_files_iter(working_directory.path())
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        lazily_matched.sort();
+        let mut eagerly_matched = pattern.matching_files(working_directory.path())?;
+        eagerly_matched.sort();
+        assert_eq!(lazily_matched.len(), 50);
+        assert_eq!(lazily_matched, eagerly_matched);
+
+        let mut missing_directory = SourcePathPattern::from_str("missing/*")
+            .unwrap()
+            .matching_files_iter(working_directory.path())
+            .collect::<Vec<_>>();
+        assert_eq!(missing_directory.len(), 1);
+        assert!(missing_directory.pop().unwrap().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn walk_is_lazy() -> anyhow::Result<()> {
+        const FILES_COUNT: usize = 20_000;
+        let working_directory = TempDir::new("shada")?;
+        for index in 0..FILES_COUNT {
+            File::create(working_directory.path().join(format!("record-{index}.txt")))?;
+        }
+        let pattern = SourcePathPattern::from_str("record-*.txt").unwrap();
+        let matches_number = Cell::new(0);
+        let mut entries = pattern.walk(
+            working_directory.path(),
+            &MatchOptions::default(),
+            |_, _| matches_number.set(matches_number.get() + 1),
+        );
+        for expected_matches_number in 1..=3 {
+            entries.next().transpose()?;
+            assert_eq!(matches_number.get(), expected_matches_number);
+        }
+        assert_eq!(entries.count(), FILES_COUNT - 3);
+        assert_eq!(matches_number.get(), FILES_COUNT);
+        Ok(())
+    }
+
+    #[test]
+    fn braces() {
+        let working_directory =
+            TempDir::new("androzani").expect("Failed to create a temporary directory");
+        for filename in [
+            "peri.png",
+            "doctor.jpg",
+            "sharaz.gif",
+            "file1.txt",
+            "file2.txt",
+            "file3.txt",
+        ] {
+            let _ = File::create(working_directory.path().join(filename));
+        }
+        let matching_files = |pattern: &str| {
+            let mut files = SourcePathPattern::from_str(pattern)
+                .unwrap()
+                .matching_files(working_directory.path())
+                .expect("Shouldn't fail");
+            files.sort();
+            files
+        };
+        assert_eq!(
+            matching_files("*.{png,jpg}"),
+            vec![
+                ("doctor.jpg".into(), vec!["doctor".to_string()]),
+                ("peri.png".into(), vec!["peri".to_string()]),
+            ]
+        );
+        assert_eq!(
+            matching_files("file{1,2}.txt"),
+            vec![("file1.txt".into(), vec![]), ("file2.txt".into(), vec![])]
+        );
+    }
+
+    #[test]
+    fn recursive() {
+        let working_directory =
+            TempDir::new("logopolis").expect("Failed to create a temporary directory");
+        for directory in ["src/lib/nested", "src/bin", "other"] {
+            std::fs::create_dir_all(working_directory.path().join(directory))
+                .expect("Failed to create a directory");
+        }
+        for filename in [
+            "src/main.rs",
+            "src/lib/mod.rs",
+            "src/lib/nested/deep.rs",
+            "src/bin/tool.rs",
+            "src/bin/readme.md",
+            "other/skipped.rs",
+        ] {
+            let _ = File::create(working_directory.path().join(filename));
+        }
+        let mut matched_files = SourcePathPattern::from_str("src/**/*.rs")
+            .unwrap()
+            .matching_files(working_directory.path())
+            .expect("Shouldn't fail");
+        matched_files.sort();
+        assert_eq!(
+            matched_files,
+            [
+                ("src/bin/tool.rs", "tool", "bin"),
+                ("src/lib/mod.rs", "mod", "lib"),
+                ("src/lib/nested/deep.rs", "deep", "lib/nested"),
+                ("src/main.rs", "main", ""),
+            ]
+            .map(|(path, filename_fragment, directory_fragment)| (
+                path.into(),
+                vec![
+                    filename_fragment.to_string(),
+                    directory_fragment.to_string()
+                ]
+            ))
+        );
+    }
+
+    #[test]
+    fn hidden() {
+        let working_directory =
+            TempDir::new("karn").expect("Failed to create a temporary directory");
+        std::fs::create_dir_all(working_directory.path().join(".cache"))
+            .expect("Failed to create a directory");
+        for filename in [".config", "notes", ".cache/sisterhood"] {
+            let _ = File::create(working_directory.path().join(filename));
+        }
+        let matching_names = |pattern: SourcePathPattern, options: MatchOptions| {
+            let mut names = pattern
+                .matching_entries(working_directory.path(), &options)
+                .expect("Shouldn't fail")
+                .matched
+                .into_iter()
+                .map(|(path, _)| path.to_str().unwrap().to_string())
+                .collect::<Vec<_>>();
+            names.sort();
+            names
+        };
+        let hidden = MatchOptions {
+            include_hidden: true,
+            ..Default::default()
+        };
+        let star = || SourcePathPattern::from_str("*").unwrap();
+        assert_eq!(matching_names(star(), MatchOptions::default()), ["notes"]);
+        assert_eq!(matching_names(star(), hidden), [".config", "notes"]);
+        for dot_pattern in [
+            SourcePathPattern::from_str(".*").unwrap(),
+            SourcePathPattern::from_str("{.c*,x*}").unwrap(),
+            SourcePathPattern::from_regex(r"\.(.*)").unwrap(),
+        ] {
+            assert_eq!(
+                matching_names(dot_pattern, MatchOptions::default()),
+                [".config"]
+            );
+        }
+        assert_eq!(
+            matching_names(
+                SourcePathPattern::from_regex(".*").unwrap(),
+                MatchOptions::default()
+            ),
+            ["notes"]
+        );
+        let recursive = || SourcePathPattern::from_str("**/*s*").unwrap();
+        assert_eq!(
+            matching_names(recursive(), MatchOptions::default()),
+            ["notes"]
+        );
+        assert_eq!(
+            matching_names(recursive(), hidden),
+            [".cache/sisterhood", "notes"]
+        );
+    }
+
+    #[test]
+    fn hidden_braces() -> anyhow::Result<()> {
+        let working_directory = TempDir::new("karn")?;
+        for filename in [".cfg", ".bashrc", "notes"] {
+            File::create(working_directory.path().join(filename))?;
+        }
+        let mut matched = SourcePathPattern::from_str("{.c*,*}")
+            .unwrap()
+            .matching_files(working_directory.path())?
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect::<Vec<_>>();
+        matched.sort();
+        assert_eq!(matched, [".cfg", "notes"].map(PathBuf::from));
+        Ok(())
+    }
+
+    #[test]
+    fn path_glob() {
+        let working_directory =
+            TempDir::new("androzani").expect("Failed to create a temporary directory");
+        for directory in ["notes/abc", "notes/abd/nested", "notes/b", "notes/.ax"] {
+            std::fs::create_dir_all(working_directory.path().join(directory))
+                .expect("Failed to create a directory");
+        }
+        for filename in [
+            "notes/top.txt",
+            "notes/abc/x.txt",
+            "notes/abd/y.txt",
+            "notes/abd/z.md",
+            "notes/abd/nested/deep.txt",
+            "notes/b/w.txt",
+            "notes/.ax/hidden.txt",
+        ] {
+            let _ = File::create(working_directory.path().join(filename));
+        }
+        let matched_files = |pattern: &str, options: MatchOptions| {
+            let mut matched = SourcePathPattern::from_path_glob(pattern)
+                .unwrap()
+                .matching_entries(working_directory.path(), &options)
+                .expect("Shouldn't fail")
+                .matched;
+            matched.sort();
+            matched
+        };
+        let expected = |files: &[(&str, &[&str])]| {
+            files
+                .iter()
+                .map(|(path, fragments)| {
+                    (
+                        path.into(),
+                        fragments
+                            .iter()
+                            .map(|fragment| fragment.to_string())
+                            .collect(),
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            matched_files("notes/a*/*.txt", MatchOptions::default()),
+            expected(&[
+                ("notes/abc/x.txt", &["bc", "x"]),
+                ("notes/abd/y.txt", &["bd", "y"])
+            ])
+        );
+        assert_eq!(
+            matched_files("notes/*b*/*.txt", MatchOptions::default()),
+            expected(&[
+                ("notes/abc/x.txt", &["a", "c", "x"]),
+                ("notes/abd/y.txt", &["a", "d", "y"]),
+                ("notes/b/w.txt", &["", "", "w"])
+            ])
+        );
+        assert_eq!(
+            matched_files("notes/*/*/*.txt", MatchOptions::default()),
+            expected(&[("notes/abd/nested/deep.txt", &["abd", "nested", "deep"])])
+        );
+        assert_eq!(
+            matched_files("notes/.*/*.txt", MatchOptions::default()),
+            expected(&[("notes/.ax/hidden.txt", &["ax", "hidden"])])
+        );
+        assert_eq!(
+            matched_files(
+                "notes/*x/*.txt",
+                MatchOptions {
+                    include_hidden: true,
+                    ..Default::default()
+                }
+            ),
+            expected(&[("notes/.ax/hidden.txt", &[".a", "hidden"])])
+        );
+        assert_eq!(
+            matched_files("notes/*.txt", MatchOptions::default()),
+            expected(&[("notes/top.txt", &["top"])])
+        );
+    }
+
+    #[test]
+    fn max_depth() {
+        let working_directory =
+            TempDir::new("kasterborous").expect("Failed to create a temporary directory");
+        std::fs::create_dir_all(working_directory.path().join("src/a/b"))
+            .expect("Failed to create a directory");
+        for filename in ["top.rs", "src/root.rs", "src/a/one.rs", "src/a/b/two.rs"] {
+            let _ = File::create(working_directory.path().join(filename));
+        }
+        let pattern = SourcePathPattern::from_str("src/**/*.rs").unwrap();
+        let matching_names = |max_depth| {
+            let mut names = pattern
+                .matching_entries(
+                    working_directory.path(),
+                    &MatchOptions {
+                        max_depth,
+                        ..Default::default()
+                    },
+                )
+                .expect("Shouldn't fail")
+                .matched
+                .into_iter()
+                .map(|(path, _)| path.to_str().unwrap().to_string())
+                .collect::<Vec<_>>();
+            names.sort();
+            names
+        };
+        assert_eq!(matching_names(Some(0)), ["src/root.rs"]);
+        assert_eq!(matching_names(Some(1)), ["src/a/one.rs", "src/root.rs"]);
+        assert_eq!(
+            matching_names(Some(2)),
+            ["src/a/b/two.rs", "src/a/one.rs", "src/root.rs"]
+        );
+        assert_eq!(matching_names(None), matching_names(Some(2)));
+    }
+
+    #[test]
+    fn regex() {
+        let working_directory =
+            TempDir::new("trenzalore").expect("Failed to create a temporary directory");
+        for filename in [
+            "11-smith.log",
+            "12-capaldi.log",
+            "13-whittaker.txt",
+            "war.log",
+        ] {
+            let _ = File::create(working_directory.path().join(filename));
+        }
+        let mut matched_files = SourcePathPattern::from_regex(r"(\d+)-(.*)\.log")
+            .unwrap()
+            .matching_files(working_directory.path())
+            .expect("Shouldn't fail");
+        matched_files.sort();
+        assert_eq!(
+            matched_files,
+            vec![
+                (
+                    "11-smith.log".into(),
+                    vec!["11".to_string(), "smith".to_string()]
+                ),
+                (
+                    "12-capaldi.log".into(),
+                    vec!["12".to_string(), "capaldi".to_string()]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn including_directories() {
+        let working_directory =
+            TempDir::new("skaro").expect("Failed to create a temporary directory");
+        for dalek in ["dalek-sec", "dalek-caan", "dalek-thay"] {
+            std::fs::create_dir(working_directory.path().join(dalek))
+                .expect("Failed to create a directory");
+        }
+        let _ = File::create(working_directory.path().join("dalek-emperor"));
+
+        let pattern = SourcePathPattern::from_str("dalek-*").unwrap();
+        assert_eq!(
+            pattern
+                .matching_files(working_directory.path())
+                .expect("Shouldn't fail"),
+            vec![("dalek-emperor".into(), vec!["emperor".to_string()])]
+        );
+
+        let mut matched_entries = pattern
+            .matching_entries(
+                working_directory.path(),
+                &MatchOptions {
+                    include_directories: true,
+                    ..Default::default()
+                },
+            )
+            .expect("Shouldn't fail")
+            .matched;
+        matched_entries.sort();
+        assert_eq!(
+            matched_entries,
+            ["caan", "emperor", "sec", "thay"]
+                .map(|name| (("dalek-".to_string() + name).into(), vec![name.to_string()]))
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks() {
+        use std::os::unix::fs::symlink;
+
+        let working_directory =
+            TempDir::new("telos").expect("Failed to create a temporary directory");
+        let path = |name: &str| working_directory.path().join(name);
+        let _ = File::create(path("tardis"));
+        std::fs::create_dir(path("console-room")).expect("Failed to create a directory");
+        symlink(path("tardis"), path("link-tardis")).expect("Failed to create a symlink");
+        symlink(path("console-room"), path("link-room")).expect("Failed to create a symlink");
+        symlink(path("missing"), path("link-missing")).expect("Failed to create a symlink");
+
+        let pattern = SourcePathPattern::from_str("link-*").unwrap();
+        let matching_names = |options: MatchOptions| {
+            let mut names = pattern
+                .matching_entries(working_directory.path(), &options)
+                .expect("Shouldn't fail")
+                .matched
+                .into_iter()
+                .map(|(_, match_info)| match_info[0].clone())
+                .collect::<Vec<_>>();
+            names.sort();
+            names
+        };
+        assert_eq!(
+            matching_names(MatchOptions::default()),
+            ["missing", "room", "tardis"]
+        );
+        assert_eq!(
+            matching_names(MatchOptions {
+                follow_symlinks: true,
+                ..Default::default()
+            }),
+            ["tardis"]
+        );
+        assert_eq!(
+            matching_names(MatchOptions {
+                include_directories: true,
+                follow_symlinks: true,
+                ..Default::default()
+            }),
+            ["room", "tardis"]
+        );
+    }
+
+    #[test]
+    fn matched_directories_arent_descended() -> anyhow::Result<()> {
+        let working_directory = TempDir::new("mondas")?;
+        std::fs::create_dir_all(working_directory.path().join("src/a/b"))?;
+        File::create(working_directory.path().join("src/a/b/c.txt"))?;
+        File::create(working_directory.path().join("src/d.txt"))?;
+        let pattern = SourcePathPattern::from_str("src/**/*").unwrap();
+        let mut matched = pattern
+            .matching_entries(
+                working_directory.path(),
+                &MatchOptions {
+                    include_directories: true,
+                    ..Default::default()
+                },
+            )?
+            .matched
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect::<Vec<_>>();
+        matched.sort();
+        assert_eq!(matched, ["src/a", "src/d.txt"].map(PathBuf::from));
+
+        let mut matched = pattern
+            .matching_files(working_directory.path())?
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect::<Vec<_>>();
+        matched.sort();
+        assert_eq!(matched, ["src/a/b/c.txt", "src/d.txt"].map(PathBuf::from));
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn non_utf8_names() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt, path::PathBuf};
+
+        let working_directory =
+            TempDir::new("karn").expect("Failed to create a temporary directory");
+        let non_utf8_name = OsStr::from_bytes(b"sisterhood-\xff.txt");
+        for filename in [OsStr::new("sisterhood-ohica.txt"), non_utf8_name] {
+            let _ = File::create(working_directory.path().join(filename));
+        }
+        let entries = SourcePathPattern::from_str("sisterhood-*.txt")
+            .unwrap()
+            .matching_entries(working_directory.path(), &MatchOptions::default())
+            .expect("Shouldn't fail");
+        assert_eq!(
+            entries.matched,
+            vec![("sisterhood-ohica.txt".into(), vec!["ohica".to_string()])]
+        );
+        assert_eq!(entries.non_utf8, vec![PathBuf::from(non_utf8_name)]);
+    }
+}
+
+#[cfg(test)]
+mod test_from_matcher {
+    use super::{MatchOptions, SourcePathPattern};
+    use crate::{GlobStarPattern, Matcher};
+    use std::{fs::File, path::PathBuf};
+    use tempdir::TempDir;
+
+    /// Matches only the given filename and captures nothing
+    #[derive(Debug)]
+    struct ExactMatcher(&'static str);
+
+    impl Matcher for ExactMatcher {
+        fn match_string<'a>(&self, string: &'a str) -> Option<Vec<&'a str>> {
+            (string == self.0).then(Vec::new)
+        }
+
+        fn wildcards_number(&self) -> usize {
+            0
+        }
+    }
+
+    fn generate_files() -> anyhow::Result<TempDir> {
+        let working_directory = TempDir::new("skaro")?;
+        std::fs::create_dir_all(working_directory.path().join("daleks/supreme"))?;
+        for path in [
+            "daleks/sec.txt",
+            "daleks/caan.txt",
+            "daleks/supreme/sec.txt",
+        ] {
+            File::create(working_directory.path().join(path))?;
+        }
+        Ok(working_directory)
+    }
+
+    #[test]
+    fn glob() -> anyhow::Result<()> {
+        let working_directory = generate_files()?;
+        let pattern = SourcePathPattern::from_matcher("daleks", GlobStarPattern::from("*.txt"));
+        assert_eq!(pattern.wildcards_number(), 1);
+        let mut matched = pattern.matching_files(working_directory.path())?;
+        matched.sort();
+        assert_eq!(
+            matched,
+            vec![
+                (PathBuf::from("daleks/caan.txt"), vec!["caan".to_string()]),
+                (PathBuf::from("daleks/sec.txt"), vec!["sec".to_string()]),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn exact() -> anyhow::Result<()> {
+        let working_directory = generate_files()?;
+        let pattern = SourcePathPattern::from_matcher("daleks/**", ExactMatcher("sec.txt"));
+        assert_eq!(pattern.wildcards_number(), 1);
+        assert_eq!(pattern.to_string(), "daleks/**/ExactMatcher(\"sec.txt\")");
+        let mut matched = pattern.matching_files(working_directory.path())?;
+        matched.sort();
+        assert_eq!(
+            matched,
+            vec![
+                (PathBuf::from("daleks/sec.txt"), vec![String::new()]),
+                (
+                    PathBuf::from("daleks/supreme/sec.txt"),
+                    vec!["supreme".to_string()]
+                ),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn hidden() -> anyhow::Result<()> {
+        let working_directory = generate_files()?;
+        File::create(working_directory.path().join("daleks/.davros"))?;
+        let pattern = SourcePathPattern::from_matcher("daleks", ExactMatcher(".davros"));
+        assert!(pattern.matching_files(working_directory.path())?.is_empty());
+        let options = MatchOptions {
+            include_hidden: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            pattern
+                .matching_entries(working_directory.path(), &options)?
+                .matched,
+            vec![(PathBuf::from("daleks/.davros"), vec![])]
+        );
+
+        let pattern = SourcePathPattern::from_matcher("daleks", GlobStarPattern::from(".*"));
+        assert_eq!(
+            pattern.matching_files(working_directory.path())?,
+            vec![(PathBuf::from("daleks/.davros"), vec!["davros".to_string()])]
+        );
+        Ok(())
+    }
 }
 
 impl FromStr for SourcePathPattern {
     type Err = &'static str;
 
     fn from_str(string: &str) -> Result<Self, Self::Err> {
-        let (directory_str, filename_pattern_str) = string.split_at(
-            string
-                .rfind('/')
-                .map_or(0, |slash_position| slash_position + 1),
-        );
+        let (directory_str, filename_pattern_str) = split_directory(string);
+        let (directory_str, recursive) = split_recursive(directory_str);
         if directory_str.contains('*') {
-            return Err("'*'-wildcard can only appear in a filename");
+            return Err(
+                "'*'-wildcard can only appear in a filename, '**' can only be the last directory",
+            );
         }
         Ok(Self {
-            directory: PathBuf::from(directory_str),
-            filename_pattern: GlobStarPattern::from(filename_pattern_str),
+            directory: expand_tilde(directory_str, std::env::var_os("HOME")),
+            recursive,
+            filename_pattern: FilenamePattern::glob(filename_pattern_str)?,
         })
     }
 }
 
+#[test]
+fn test_tilde_expansion() {
+    use crate::DestinationPathTemplate;
+
+    let home = || Some(OsString::from("/home/donna"));
+    assert_eq!(
+        expand_tilde("~/Downloads/", home()),
+        PathBuf::from("/home/donna/Downloads/")
+    );
+    assert_eq!(expand_tilde("~/", home()), PathBuf::from("/home/donna/"));
+    assert_eq!(expand_tilde("~", home()), PathBuf::from("/home/donna"));
+    for not_expanded in ["~susan/", "docs/~/", "a~/", ""] {
+        assert_eq!(
+            expand_tilde(not_expanded, home()),
+            PathBuf::from(not_expanded)
+        );
+    }
+    assert_eq!(
+        expand_tilde("~/Downloads/", None),
+        PathBuf::from("~/Downloads/")
+    );
+
+    // Patterns take the home directory from the environment, which is only read here
+    let expected = expand_tilde("~/", std::env::var_os("HOME"));
+    assert_eq!(
+        SourcePathPattern::from_str("~/*.pdf").unwrap().directory,
+        expected
+    );
+    assert_eq!(
+        SourcePathPattern::from_regex(r"~/(.*)\.pdf")
+            .unwrap()
+            .directory,
+        expected
+    );
+    assert_eq!(
+        SourcePathPattern::from_str("~*.pdf").unwrap().directory,
+        PathBuf::new()
+    );
+    assert_eq!(
+        DestinationPathTemplate::compile("~/#1.pdf", 1).directory,
+        expected
+    );
+    assert_eq!(
+        DestinationPathTemplate::compile("~ian/#1.pdf", 1).directory,
+        PathBuf::from("~ian/")
+    );
+}
+
 #[test]
 fn test_source_path_pattern_from_str() {
     assert_eq!(
         SourcePathPattern::from_str("doctor/in/blue/box/*.tardis"),
         Ok(SourcePathPattern {
             directory: PathBuf::from("doctor/in/blue/box/"),
-            filename_pattern: GlobStarPattern::from("*.tardis")
+            recursive: false,
+            filename_pattern: GlobStarPattern::from("*.tardis").into()
         })
     );
 
@@ -161,7 +1450,8 @@ fn test_source_path_pattern_from_str() {
         SourcePathPattern::from_str("master*dalek"),
         Ok(SourcePathPattern {
             directory: PathBuf::default(),
-            filename_pattern: GlobStarPattern::from("master*dalek")
+            recursive: false,
+            filename_pattern: GlobStarPattern::from("master*dalek").into()
         })
     );
 
@@ -169,22 +1459,70 @@ fn test_source_path_pattern_from_str() {
         SourcePathPattern::from_str("/from_root.*"),
         Ok(SourcePathPattern {
             directory: PathBuf::from("/"),
-            filename_pattern: GlobStarPattern::from("from_root.*")
+            recursive: false,
+            filename_pattern: GlobStarPattern::from("from_root.*").into()
         })
     );
 
     assert!(SourcePathPattern::from_str("b*d/pattern").is_err());
+    assert!(SourcePathPattern::from_str("**/src/*.rs").is_err());
+    assert!(SourcePathPattern::from_str("src**/*.rs").is_err());
+    assert_eq!(
+        SourcePathPattern::from_str("src/**/*.rs"),
+        Ok(SourcePathPattern {
+            directory: PathBuf::from("src/"),
+            recursive: true,
+            filename_pattern: GlobStarPattern::from("*.rs").into()
+        })
+    );
+    assert!(SourcePathPattern::from_str("{*.*,*}").is_err());
+    assert_eq!(
+        SourcePathPattern::from_str("images/*.{png,jpg}"),
+        Ok(SourcePathPattern {
+            directory: PathBuf::from("images/"),
+            recursive: false,
+            filename_pattern: FilenamePattern::Alternatives {
+                pattern: "*.{png,jpg}".to_string(),
+                alternatives: vec![
+                    GlobStarPattern::from("*.png"),
+                    GlobStarPattern::from("*.jpg")
+                ]
+            }
+        })
+    );
 }
 
 impl Display for SourcePathPattern {
     fn fmt(&self, format: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self.directory.to_str() {
-            Some(directory_str) => write!(format, "{directory_str}{}", self.filename_pattern),
+            Some(directory_str) => write!(
+                format,
+                "{directory_str}{}{}",
+                if self.recursive { "**/" } else { "" },
+                self.filename_pattern
+            ),
             None => Err(std::fmt::Error),
         }
     }
 }
 
+#[test]
+fn test_from_path_glob() {
+    let pattern = SourcePathPattern::from_path_glob("~/notes/a*/*.txt").unwrap();
+    assert!(pattern.directory.ends_with("notes/"));
+    assert_eq!(pattern.filename_pattern.to_string(), "a*/*.txt");
+    let pattern = SourcePathPattern::from_path_glob("notes/*.txt").unwrap();
+    assert_eq!(pattern.directory, PathBuf::from("notes/"));
+    assert_eq!(pattern, SourcePathPattern::from_str("notes/*.txt").unwrap());
+    assert_eq!(
+        SourcePathPattern::from_path_glob("notes/a*/b/c.txt")
+            .unwrap()
+            .to_string(),
+        "notes/a*/b/c.txt"
+    );
+    assert!(SourcePathPattern::from_path_glob("notes/**/*.txt").is_err());
+}
+
 #[test]
 fn test_display() {
     for pattern in [
@@ -194,6 +1532,10 @@ fn test_display() {
         "/hello/world",
         "empty/filename/",
         "path/*.png",
+        "path/*.{png,jpg}",
+        "{malformed",
+        "**/*.rs",
+        "src/**/*.rs",
     ] {
         assert_eq!(
             SourcePathPattern::from_str(pattern).unwrap().to_string(),
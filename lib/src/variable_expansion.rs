@@ -0,0 +1,118 @@
+use std::{borrow::Cow, fmt::Display};
+
+/// Error returned if a template refers to a variable which isn't set
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownVariableError {
+    /// Name of the variable
+    pub name: String,
+}
+
+impl Display for UnknownVariableError {
+    fn fmt(&self, format: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            format,
+            "Environment variable ${} is not set, use $$ for a literal '$'",
+            self.name
+        )
+    }
+}
+
+impl std::error::Error for UnknownVariableError {}
+
+/// Replaces `$NAME` and `${NAME}` in `text` by values returned by `lookup`, where `NAME` consists
+/// of ASCII letters, digits and underscores and doesn't start with a digit. `$$` is replaced by
+/// a single `$`, any other `$`, e.g. an unclosed `${`, is left as is. Fails if `lookup` returns
+/// `None` for some variable.
+pub(crate) fn expand_variables<'a>(
+    text: &'a str,
+    lookup: &impl Fn(&str) -> Option<String>,
+) -> Result<Cow<'a, str>, UnknownVariableError> {
+    if !text.contains('$') {
+        return Ok(Cow::Borrowed(text));
+    }
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(dollar_position) = rest.find('$') {
+        result.push_str(&rest[..dollar_position]);
+        rest = &rest[dollar_position + 1..];
+        if let Some(after_dollar) = rest.strip_prefix('$') {
+            result.push('$');
+            rest = after_dollar;
+            continue;
+        }
+        let (name, length) = match rest.strip_prefix('{') {
+            Some(braced) => {
+                let name_length = braced.find('}').unwrap_or(0);
+                (&braced[..name_length], name_length + 2)
+            }
+            None => {
+                let name_length = rest
+                    .find(|symbol: char| !(symbol.is_ascii_alphanumeric() || symbol == '_'))
+                    .unwrap_or(rest.len());
+                (&rest[..name_length], name_length)
+            }
+        };
+        if !is_variable_name(name) {
+            result.push('$');
+            continue;
+        }
+        let value = lookup(name).ok_or_else(|| UnknownVariableError {
+            name: name.to_string(),
+        })?;
+        result.push_str(&value);
+        rest = &rest[length..];
+    }
+    result.push_str(rest);
+    Ok(Cow::Owned(result))
+}
+
+fn is_variable_name(name: &str) -> bool {
+    name.starts_with(|symbol: char| symbol.is_ascii_alphabetic() || symbol == '_')
+        && name
+            .chars()
+            .all(|symbol| symbol.is_ascii_alphanumeric() || symbol == '_')
+}
+
+#[cfg(test)]
+mod test_expand_variables {
+    use super::{expand_variables, UnknownVariableError};
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "ARCHIVE_DIR" => Some("/srv/archive".to_string()),
+            "x1" => Some("one".to_string()),
+            "EMPTY" => Some(String::new()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn it_works() {
+        for (text, expected) in [
+            ("plain#1", "plain#1"),
+            ("$ARCHIVE_DIR/", "/srv/archive/"),
+            ("${ARCHIVE_DIR}s", "/srv/archives"),
+            ("$x1.$x1", "one.one"),
+            ("a$EMPTY-b", "a-b"),
+            ("$$ARCHIVE_DIR", "$ARCHIVE_DIR"),
+            ("cost: 5$", "cost: 5$"),
+            ("$1-$-", "$1-$-"),
+            ("${ARCHIVE_DIR", "${ARCHIVE_DIR"),
+            ("${}", "${}"),
+        ] {
+            assert_eq!(expand_variables(text, &lookup).unwrap(), expected, "{text}");
+        }
+    }
+
+    #[test]
+    fn unknown_variable() {
+        for text in ["$MISSING/", "${MISSING}", "$x1$MISSING"] {
+            assert_eq!(
+                expand_variables(text, &lookup),
+                Err(UnknownVariableError {
+                    name: "MISSING".to_string()
+                })
+            );
+        }
+    }
+}
@@ -0,0 +1,44 @@
+use std::io::{self, BufRead, Write};
+
+/// Writes `question` followed by ` [y/N] ` to `writer` and reads an answer from `reader`.
+/// Returns `true` if the answer is `y` or `yes` in any case, any other answer, including
+/// the end of input, means no.
+pub fn confirm(
+    question: &str,
+    mut reader: impl BufRead,
+    mut writer: impl Write,
+) -> io::Result<bool> {
+    write!(writer, "{question} [y/N] ")?;
+    writer.flush()?;
+    let mut answer = String::new();
+    reader.read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+#[cfg(test)]
+mod test_confirm {
+    use super::confirm;
+
+    #[test]
+    fn it_works() -> std::io::Result<()> {
+        for (answer, expected) in [
+            ("y\n", true),
+            ("yes\n", true),
+            (" Y \n", true),
+            ("YES", true),
+            ("n\n", false),
+            ("\n", false),
+            ("", false),
+            ("yep\n", false),
+        ] {
+            let mut output = vec![];
+            assert_eq!(
+                confirm("Proceed?", answer.as_bytes(), &mut output)?,
+                expected,
+                "{answer:?}"
+            );
+            assert_eq!(output, b"Proceed? [y/N] ");
+        }
+        Ok(())
+    }
+}
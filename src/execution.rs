@@ -0,0 +1,985 @@
+use std::{
+    collections::HashSet,
+    fs::{File, FileTimes},
+    io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc,
+    },
+    time::Duration,
+};
+
+use clap::ValueEnum;
+use color_print::cformat;
+
+use crate::{
+    output::{Reporter, Summary},
+    undo_log::{reversing_moves, UndoLogWriter},
+};
+
+/// Result of a single move
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MoveStatus {
+    /// The file has been moved
+    Done,
+    /// The destination already exists, so the file hasn't been moved
+    Skip,
+    /// Moving has failed with an error
+    Failed(String),
+    /// Moving has failed because the file is in use, see [`is_busy`]
+    Busy(String),
+    /// The file is to be moved, but it's a dry run
+    Planned,
+    /// The source and the destination are the same path, so there is nothing to do
+    Unchanged,
+    /// The move hasn't been attempted because another one has failed,
+    /// see [`ExecutionOptions::stop_on_failure`]
+    Cancelled,
+    /// The file has been moved, but then moved back because another move has failed,
+    /// see [`roll_back`]
+    RolledBack,
+}
+
+/// A move with its result
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MoveRecord {
+    pub source: PathBuf,
+    pub destination: PathBuf,
+    pub status: MoveStatus,
+    /// Path the replaced destination has been moved to
+    pub backup: Option<PathBuf>,
+}
+
+/// What to do with a file whose destination already exists
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// Leave the file where it is
+    #[default]
+    Skip,
+    /// Replace the existing destination
+    Overwrite,
+    /// Move the file to a free name with a numeric suffix, e.g. name-1.txt
+    Rename,
+    /// Don't move any file if some destination exists
+    Fail,
+}
+
+/// Options controlling how the moves are performed
+#[derive(Debug, Clone, Default)]
+pub struct ExecutionOptions {
+    /// What to do if a destination already exists
+    pub on_conflict: ConflictPolicy,
+    /// Move replaced destinations aside instead of overwriting them, see [`backup_path`]
+    pub backup_suffix: Option<String>,
+    /// Don't touch the filesystem, only report what would be done
+    pub dry_run: bool,
+    /// Number of threads performing the moves, `0` means the same as `1`
+    pub jobs: usize,
+    /// Copy files instead of moving them
+    pub copy: bool,
+    /// Set access and modification times of copies to the ones of the sources
+    pub preserve_timestamps: bool,
+    /// Number of retries of a move failed because the file is in use, see [`retry`]
+    pub retries: usize,
+    /// Don't start new moves after a move has failed, the rest are [`MoveStatus::Cancelled`]
+    pub stop_on_failure: bool,
+}
+
+/// Delay before the first retry of a move, it's doubled before each next one
+const RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Moves a single file according to `options`
+pub fn move_file(source: PathBuf, destination: PathBuf, options: &ExecutionOptions) -> MoveRecord {
+    let mut record = MoveRecord {
+        source,
+        destination,
+        status: MoveStatus::Done,
+        backup: None,
+    };
+    if is_same_path(&record.source, &record.destination) {
+        record.status = MoveStatus::Unchanged;
+        return record;
+    }
+    // Unlike `exists`, doesn't follow symlinks, so a dangling symlink isn't silently replaced
+    let mut destination_exists = record.destination.symlink_metadata().is_ok();
+    if destination_exists {
+        match options.on_conflict {
+            ConflictPolicy::Skip => {
+                record.status = MoveStatus::Skip;
+                return record;
+            }
+            ConflictPolicy::Fail => {
+                record.status = MoveStatus::Failed("Destination already exists".to_owned());
+                return record;
+            }
+            ConflictPolicy::Rename => {
+                record.destination = numbered_path(&record.destination);
+                destination_exists = false;
+            }
+            ConflictPolicy::Overwrite => {}
+        }
+    }
+    if options.dry_run {
+        record.status = MoveStatus::Planned;
+        return record;
+    }
+    if let (Some(suffix), true) = (&options.backup_suffix, destination_exists) {
+        let backup = backup_path(&record.destination, suffix);
+        if let Err(error) = std::fs::rename(&record.destination, &backup) {
+            record.status = MoveStatus::Failed(format!("Failed to create backup: {error:#}"));
+            return record;
+        }
+        record.backup = Some(backup);
+    }
+    let result = retry(options.retries, RETRY_DELAY, || {
+        if options.copy {
+            copy(
+                &record.source,
+                &record.destination,
+                options.preserve_timestamps,
+            )
+        } else {
+            rename(&record.source, &record.destination)
+        }
+    });
+    if let Err(error) = result {
+        record.status = if is_busy(&error) {
+            MoveStatus::Busy(format!("{error:#}"))
+        } else {
+            MoveStatus::Failed(format!("{error:#}"))
+        };
+        if let Some(backup) = record.backup.take() {
+            if let Err(restore_error) = std::fs::rename(&backup, &record.destination) {
+                record.status = MoveStatus::Failed(format!(
+                    "{error:#}; the replaced file is left at {backup:#?}: {restore_error:#}"
+                ));
+                record.backup = Some(backup);
+            }
+        }
+    }
+    record
+}
+
+/// Calls `operation` until it succeeds, fails with an error other than [`is_busy`] one,
+/// or is retried `retries` times. Sleeps `delay` before the first retry and twice as long
+/// before each next one. Returns the result of the last call.
+pub fn retry<T>(
+    retries: usize,
+    delay: Duration,
+    mut operation: impl FnMut() -> io::Result<T>,
+) -> io::Result<T> {
+    let mut delay = delay;
+    for _ in 0..retries {
+        match operation() {
+            Err(error) if is_busy(&error) => {
+                std::thread::sleep(delay);
+                delay *= 2;
+            }
+            result => return result,
+        }
+    }
+    operation()
+}
+
+/// Checks whether `error` means that the file is used by another process,
+/// so the operation may succeed later
+pub fn is_busy(error: &io::Error) -> bool {
+    // Sharing and lock violations on Windows
+    const WINDOWS_BUSY_ERRORS: [i32; 2] = [32, 33];
+    matches!(
+        error.kind(),
+        io::ErrorKind::ResourceBusy | io::ErrorKind::ExecutableFileBusy
+    ) || cfg!(windows)
+        && error
+            .raw_os_error()
+            .is_some_and(|code| WINDOWS_BUSY_ERRORS.contains(&code))
+}
+
+/// Checks whether `left` and `right` refer to the same directory entry, e.g. `./a` and `a`.
+/// Only parent directories are canonicalized, so a symlink and its target are different paths.
+fn is_same_path(left: &Path, right: &Path) -> bool {
+    let canonicalize = |path: &Path| -> Option<PathBuf> {
+        let parent = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        Some(std::fs::canonicalize(parent).ok()?.join(path.file_name()?))
+    };
+    match (canonicalize(left), canonicalize(right)) {
+        (Some(left), Some(right)) => left == right,
+        _ => false,
+    }
+}
+
+/// Returns a path to move `destination` to before replacing it: `destination` with appended
+/// `suffix`, or `destination.~N~` with the smallest possible `N` if the former already exists.
+pub fn backup_path(destination: &Path, suffix: &str) -> PathBuf {
+    let with_suffix = |suffix: &str| {
+        let mut path = destination.as_os_str().to_os_string();
+        path.push(suffix);
+        PathBuf::from(path)
+    };
+    let simple_backup = with_suffix(suffix);
+    if !simple_backup.exists() {
+        return simple_backup;
+    }
+    (1..)
+        .map(|index| with_suffix(&format!(".~{index}~")))
+        .find(|numbered_backup| !numbered_backup.exists())
+        .expect("there is always a free backup name")
+}
+
+/// Returns `destination` with the smallest `N` appended to the file stem, i.e. `name-N.ext`,
+/// such that the path doesn't exist
+pub fn numbered_path(destination: &Path) -> PathBuf {
+    numbered_paths(destination)
+        .find(|path| path.symlink_metadata().is_err())
+        .expect("there is always a free numbered name")
+}
+
+/// Returns `destination` with `-1`, `-2` and so on appended to the file stem
+fn numbered_paths(destination: &Path) -> impl Iterator<Item = PathBuf> + '_ {
+    let stem = destination.file_stem().unwrap_or_default();
+    (1..).map(move |index| {
+        let mut filename = stem.to_os_string();
+        filename.push(format!("-{index}"));
+        if let Some(extension) = destination.extension() {
+            filename.push(".");
+            filename.push(extension);
+        }
+        destination.with_file_name(filename)
+    })
+}
+
+/// Replaces each existing destination by a free numbered path, see [`numbered_path`], which is
+/// neither a destination of another move nor chosen for another one. Since all the names are
+/// chosen before any file is moved, the moves can be performed in parallel without two of them
+/// taking the same free name.
+pub fn reserve_numbered_paths(moves: Vec<(PathBuf, PathBuf)>) -> Vec<(PathBuf, PathBuf)> {
+    let mut taken = moves
+        .iter()
+        .map(|(_, destination)| destination.clone())
+        .collect::<HashSet<_>>();
+    moves
+        .into_iter()
+        .map(|(source, destination)| {
+            if destination.symlink_metadata().is_err() || is_same_path(&source, &destination) {
+                return (source, destination);
+            }
+            let numbered = numbered_paths(&destination)
+                .find(|path| path.symlink_metadata().is_err() && !taken.contains(path))
+                .expect("there is always a free numbered name");
+            taken.insert(numbered.clone());
+            (source, numbered)
+        })
+        .collect()
+}
+
+/// Returns the first move whose destination already exists and differs from its source
+pub fn find_conflict(moves: &[(PathBuf, PathBuf)]) -> Option<&(PathBuf, PathBuf)> {
+    moves.iter().find(|(source, destination)| {
+        destination.symlink_metadata().is_ok() && !is_same_path(source, destination)
+    })
+}
+
+/// Renames `source` into `destination`. Since [`std::fs::rename`] doesn't work across
+/// filesystems, a file or a symlink is copied and then removed in this case.
+fn rename(source: &Path, destination: &Path) -> io::Result<()> {
+    match std::fs::rename(source, destination) {
+        Err(error)
+            if error.kind() == io::ErrorKind::CrossesDevices
+                && source
+                    .symlink_metadata()
+                    .is_ok_and(|metadata| metadata.is_file() || metadata.is_symlink()) =>
+        {
+            copy_and_remove(source, destination)
+        }
+        result => result,
+    }
+}
+
+/// Copies the `source` file into `destination` and removes `source` afterwards. A symlink is
+/// copied as a link pointing to the same target, see [`copy_symlink`].
+/// If the source cannot be removed, the copy is removed, so only one of the files exists.
+fn copy_and_remove(source: &Path, destination: &Path) -> io::Result<()> {
+    if source.symlink_metadata()?.is_symlink() {
+        copy_symlink(source, destination)?;
+    } else {
+        std::fs::copy(source, destination)?;
+    }
+    std::fs::remove_file(source).inspect_err(|_| {
+        let _ = std::fs::remove_file(destination);
+    })
+}
+
+/// Creates a symlink at `destination` with the same target as the `source` symlink, replacing
+/// an existing `destination` like [`std::fs::rename`] does. The target isn't adjusted, so
+/// a relative one is resolved from the new location, as if the link were renamed.
+#[cfg(unix)]
+fn copy_symlink(source: &Path, destination: &Path) -> io::Result<()> {
+    let target = std::fs::read_link(source)?;
+    // The link is created aside and renamed into place, so an existing destination is kept
+    // if creating the link fails
+    let temporary = backup_path(destination, ".mmv-link");
+    std::os::unix::fs::symlink(target, &temporary)?;
+    std::fs::rename(&temporary, destination).inspect_err(|_| {
+        let _ = std::fs::remove_file(&temporary);
+    })
+}
+
+/// Symlinks aren't moved across filesystems on platforms other than Unix, since
+/// a link to a file and a link to a directory are different kinds of entries there
+#[cfg(not(unix))]
+fn copy_symlink(_source: &Path, _destination: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "moving symlinks across filesystems is not supported",
+    ))
+}
+
+/// Copies the `source` file into `destination` with its permissions. If `preserve_timestamps`
+/// is set, access and modification times are copied as well. Failing to set them is reported
+/// as a warning only, since the content is copied anyway.
+fn copy(source: &Path, destination: &Path, preserve_timestamps: bool) -> io::Result<()> {
+    let mut source_file = File::open(source)?;
+    let metadata = source_file.metadata()?;
+    let mut destination_file = File::create(destination)?;
+    io::copy(&mut source_file, &mut destination_file)?;
+    if preserve_timestamps {
+        if let Err(error) = copy_timestamps(&metadata, &destination_file) {
+            eprintln!(
+                "{}",
+                cformat!(
+                    "<yellow>Warning</>: failed to preserve timestamps of {destination:#?}: {error:#}"
+                )
+            );
+        }
+    }
+    // Permissions are set last through the open file, so that the times of a read-only copy
+    // can still be set
+    destination_file.set_permissions(metadata.permissions())
+}
+
+fn copy_timestamps(source_metadata: &std::fs::Metadata, destination: &File) -> io::Result<()> {
+    destination.set_times(
+        FileTimes::new()
+            .set_accessed(source_metadata.accessed()?)
+            .set_modified(source_metadata.modified()?),
+    )
+}
+
+/// Performs the moves passing the result of each of them to the `reporter`. Successful moves are
+/// recorded to `undo_log` if it's given. Returns results of all the moves in the same order.
+///
+/// If `options.jobs` is greater than one, the moves are split into disjoint consecutive parts
+/// which are performed in parallel. In this case [`Reporter::performed`] is called as soon as
+/// a move is done, in arbitrary order, while [`Reporter::report`] is called in the original
+/// order after all the moves are done.
+///
+/// With [`ConflictPolicy::Rename`] the numbered names are chosen before any file is moved,
+/// see [`reserve_numbered_paths`]. A destination which appears after that is skipped.
+pub fn move_files(
+    moves: Vec<(PathBuf, PathBuf)>,
+    options: &ExecutionOptions,
+    mut undo_log: Option<&mut UndoLogWriter>,
+    reporter: &mut dyn Reporter,
+) -> Vec<MoveRecord> {
+    let renaming_options;
+    let (moves, options) = if options.on_conflict == ConflictPolicy::Rename {
+        renaming_options = ExecutionOptions {
+            on_conflict: ConflictPolicy::Skip,
+            ..options.clone()
+        };
+        (reserve_numbered_paths(moves), &renaming_options)
+    } else {
+        (moves, options)
+    };
+    let is_parallel = options.jobs > 1;
+    let mut finish = |record: MoveRecord| {
+        let record = record_to_undo_log(record, undo_log.as_deref_mut());
+        reporter.performed(&record);
+        if !is_parallel {
+            reporter.report(&record);
+        }
+        record
+    };
+
+    let has_failed = AtomicBool::new(false);
+    let perform = |source: &PathBuf, destination: &PathBuf| {
+        if options.stop_on_failure && has_failed.load(Ordering::Relaxed) {
+            return MoveRecord {
+                source: source.clone(),
+                destination: destination.clone(),
+                status: MoveStatus::Cancelled,
+                backup: None,
+            };
+        }
+        let record = move_file(source.clone(), destination.clone(), options);
+        if let MoveStatus::Failed(_) | MoveStatus::Busy(_) = record.status {
+            has_failed.store(true, Ordering::Relaxed);
+        }
+        record
+    };
+
+    if !is_parallel {
+        return moves
+            .iter()
+            .map(|(source, destination)| finish(perform(source, destination)))
+            .collect();
+    }
+    let chunk_size = moves.len().div_ceil(options.jobs).max(1);
+    let mut records = std::iter::repeat_with(|| None)
+        .take(moves.len())
+        .collect::<Vec<_>>();
+    std::thread::scope(|scope| {
+        let (sender, receiver) = mpsc::channel();
+        for (chunk_index, chunk) in moves.chunks(chunk_size).enumerate() {
+            let perform = &perform;
+            let sender = sender.clone();
+            scope.spawn(move || {
+                for (offset, (source, destination)) in chunk.iter().enumerate() {
+                    let record = perform(source, destination);
+                    // The receiver is alive until all the senders are dropped
+                    let _ = sender.send((chunk_index * chunk_size + offset, record));
+                }
+            });
+        }
+        // Only the workers' senders are left, so receiving stops when all of them are done
+        drop(sender);
+        for (index, record) in receiver {
+            records[index] = Some(finish(record));
+        }
+    });
+    let records = records
+        .into_iter()
+        .map(|record| record.expect("Worker thread has panicked"))
+        .collect::<Vec<_>>();
+    for record in &records {
+        reporter.report(record);
+    }
+    records
+}
+
+/// Like [`move_files`] with a single job, but the moves are performed as soon as they are
+/// yielded by `moves`, and only the summary of their results is kept. Since the destinations
+/// aren't known in advance, `options.on_conflict` mustn't be [`ConflictPolicy::Rename`].
+pub fn move_files_iter(
+    moves: impl IntoIterator<Item = (PathBuf, PathBuf)>,
+    options: &ExecutionOptions,
+    mut undo_log: Option<&mut UndoLogWriter>,
+    reporter: &mut dyn Reporter,
+) -> Summary {
+    debug_assert_ne!(options.on_conflict, ConflictPolicy::Rename);
+    let mut summary = Summary {
+        copy: options.copy,
+        ..Default::default()
+    };
+    for (source, destination) in moves {
+        let record = record_to_undo_log(
+            move_file(source, destination, options),
+            undo_log.as_deref_mut(),
+        );
+        reporter.performed(&record);
+        reporter.report(&record);
+        summary.add(&record.status);
+    }
+    summary
+}
+
+/// Records the move to `undo_log` if it's done. If recording fails, the move is reported
+/// as failed, since it cannot be undone.
+fn record_to_undo_log(mut record: MoveRecord, undo_log: Option<&mut UndoLogWriter>) -> MoveRecord {
+    if let (MoveStatus::Done, Some(undo_log)) = (&record.status, undo_log) {
+        if let Err(error) = undo_log.record(&record) {
+            record.status = MoveStatus::Failed(format!("Moved, but not recorded: {error:#}"));
+        }
+    }
+    record
+}
+
+/// Reverses the done moves of `records` in reverse order, moving back the replaced files
+/// from their backups as well. Records of the files moved back get [`MoveStatus::RolledBack`]
+/// status. Returns records of the reversing moves which haven't been done.
+///
+/// It's a best effort: the filesystem may have been changed by somebody else since the moves.
+/// A reversing move never replaces an existing file, so a file whose original path is taken
+/// is left at its destination, and its replaced file is left at the backup path.
+pub fn roll_back(records: &mut [MoveRecord]) -> Vec<MoveRecord> {
+    let mut failed_records = vec![];
+    for record in records
+        .iter_mut()
+        .rev()
+        .filter(|record| record.status == MoveStatus::Done)
+    {
+        let mut is_moved_back = true;
+        for (index, (source, destination)) in reversing_moves(
+            &record.source,
+            &record.destination,
+            record.backup.as_deref(),
+        )
+        .into_iter()
+        .enumerate()
+        {
+            let reversing_record = move_file(source, destination, &ExecutionOptions::default());
+            if reversing_record.status != MoveStatus::Done {
+                // The first reversing move is the one of the file itself
+                is_moved_back &= index != 0;
+                failed_records.push(reversing_record);
+            }
+        }
+        if is_moved_back {
+            record.status = MoveStatus::RolledBack;
+        }
+    }
+    failed_records
+}
+
+#[cfg(test)]
+mod test_copy_and_remove {
+    use super::copy_and_remove;
+    use tempdir::TempDir;
+
+    #[test]
+    fn it_works() -> anyhow::Result<()> {
+        let directory = TempDir::new("mmv-copy")?;
+        let source = directory.path().join("source");
+        let destination = directory.path().join("destination");
+        std::fs::write(&source, "content")?;
+        copy_and_remove(&source, &destination)?;
+        assert!(!source.exists());
+        assert_eq!(std::fs::read_to_string(&destination)?, "content");
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink() -> anyhow::Result<()> {
+        let directory = TempDir::new("mmv-copy")?;
+        let source = directory.path().join("source");
+        let destination = directory.path().join("destination");
+        std::fs::write(directory.path().join("target"), "content")?;
+        std::os::unix::fs::symlink("target", &source)?;
+        std::fs::write(&destination, "replaced")?;
+        copy_and_remove(&source, &destination)?;
+        assert!(source.symlink_metadata().is_err());
+        assert!(destination.symlink_metadata()?.is_symlink());
+        assert_eq!(
+            std::fs::read_link(&destination)?,
+            std::path::Path::new("target")
+        );
+        assert_eq!(
+            std::fs::read_to_string(directory.path().join("target"))?,
+            "content"
+        );
+        assert_eq!(std::fs::read_dir(directory.path())?.count(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn missing_source() -> anyhow::Result<()> {
+        let directory = TempDir::new("mmv-copy")?;
+        let destination = directory.path().join("destination");
+        assert!(copy_and_remove(&directory.path().join("source"), &destination).is_err());
+        assert!(!destination.exists());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test_copy {
+    use super::copy;
+    use std::{
+        fs::{File, FileTimes},
+        time::{Duration, SystemTime},
+    };
+    use tempdir::TempDir;
+
+    #[test]
+    fn preserve_timestamps() -> anyhow::Result<()> {
+        let directory = TempDir::new("mmv-copy")?;
+        let source = directory.path().join("source");
+        std::fs::write(&source, "content")?;
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        File::options()
+            .write(true)
+            .open(&source)?
+            .set_times(FileTimes::new().set_modified(modified))?;
+
+        let copied = directory.path().join("copied");
+        copy(&source, &copied, false)?;
+        assert!(source.exists());
+        assert_eq!(std::fs::read_to_string(&copied)?, "content");
+        assert!(copied.metadata()?.modified()? > modified + Duration::from_secs(1));
+
+        let preserved = directory.path().join("preserved");
+        copy(&source, &preserved, true)?;
+        let difference = preserved.metadata()?.modified()?.duration_since(modified)?;
+        assert!(difference < Duration::from_secs(1));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test_retry {
+    use super::retry;
+    use std::{io, time::Duration};
+
+    fn busy() -> io::Error {
+        io::Error::from(io::ErrorKind::ResourceBusy)
+    }
+
+    #[test]
+    fn succeeds_on_second_attempt() {
+        let mut attempts = 0;
+        let result = retry(3, Duration::ZERO, || {
+            attempts += 1;
+            match attempts {
+                1 => Err(busy()),
+                _ => Ok(attempts),
+            }
+        });
+        assert_eq!(result.unwrap(), 2);
+        assert_eq!(attempts, 2);
+    }
+
+    #[test]
+    fn gives_up() {
+        let mut attempts = 0;
+        let result: io::Result<()> = retry(2, Duration::ZERO, || {
+            attempts += 1;
+            Err(busy())
+        });
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::ResourceBusy);
+        assert_eq!(attempts, 3);
+
+        let mut attempts = 0;
+        let result: io::Result<()> = retry(2, Duration::ZERO, || {
+            attempts += 1;
+            Err(io::Error::from(io::ErrorKind::NotFound))
+        });
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert_eq!(attempts, 1);
+    }
+}
+
+#[cfg(test)]
+mod test_is_same_path {
+    use super::is_same_path;
+    use tempdir::TempDir;
+
+    #[test]
+    fn it_works() -> anyhow::Result<()> {
+        let directory = TempDir::new("mmv-same")?;
+        std::fs::create_dir(directory.path().join("nested"))?;
+        let path = |relative: &str| directory.path().join(relative);
+        assert!(is_same_path(&path("a"), &path("a")));
+        assert!(is_same_path(&path("./a"), &path("a")));
+        assert!(is_same_path(&path("nested/../a"), &path("a")));
+        assert!(!is_same_path(&path("nested/a"), &path("a")));
+        assert!(!is_same_path(&path("missing/a"), &path("missing/a")));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test_roll_back {
+    use super::{move_files, roll_back, ConflictPolicy, ExecutionOptions, MoveStatus};
+    use crate::output::QuietReporter;
+    use tempdir::TempDir;
+
+    #[test]
+    fn it_works() -> anyhow::Result<()> {
+        let directory = TempDir::new("mmv-roll-back")?;
+        let path = |relative: &str| directory.path().join(relative);
+        for (file, content) in [("a", "a"), ("b", "b"), ("moved-b", "old")] {
+            std::fs::write(path(file), content)?;
+        }
+        let options = ExecutionOptions {
+            on_conflict: ConflictPolicy::Overwrite,
+            backup_suffix: Some("~".to_string()),
+            ..Default::default()
+        };
+        let mut records = move_files(
+            [("a", "moved-a"), ("b", "moved-b"), ("missing", "moved-c")]
+                .map(|(source, destination)| (path(source), path(destination)))
+                .to_vec(),
+            &options,
+            None,
+            &mut QuietReporter,
+        );
+        assert!(matches!(records[2].status, MoveStatus::Failed(_)));
+        assert_eq!(roll_back(&mut records), []);
+        assert_eq!(records[0].status, MoveStatus::RolledBack);
+        assert_eq!(records[1].status, MoveStatus::RolledBack);
+        assert_eq!(std::fs::read_to_string(path("a"))?, "a");
+        assert_eq!(std::fs::read_to_string(path("b"))?, "b");
+        assert_eq!(std::fs::read_to_string(path("moved-b"))?, "old");
+        assert!(!path("moved-a").exists());
+        assert!(!path("moved-b~").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn taken_source_is_kept() -> anyhow::Result<()> {
+        let directory = TempDir::new("mmv-roll-back")?;
+        let path = |relative: &str| directory.path().join(relative);
+        std::fs::write(path("a"), "a")?;
+        let mut records = move_files(
+            vec![(path("a"), path("moved-a"))],
+            &ExecutionOptions::default(),
+            None,
+            &mut QuietReporter,
+        );
+        std::fs::write(path("a"), "new")?;
+        let failed = roll_back(&mut records);
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].status, MoveStatus::Skip);
+        assert_eq!(records[0].status, MoveStatus::Done);
+        assert_eq!(std::fs::read_to_string(path("a"))?, "new");
+        assert_eq!(std::fs::read_to_string(path("moved-a"))?, "a");
+        Ok(())
+    }
+
+    #[test]
+    fn stop_on_failure() -> anyhow::Result<()> {
+        for jobs in [1, 2] {
+            let directory = TempDir::new("mmv-roll-back")?;
+            let path = |relative: &str| directory.path().join(relative);
+            for file in ["a", "c", "d"] {
+                std::fs::write(path(file), file)?;
+            }
+            let options = ExecutionOptions {
+                stop_on_failure: true,
+                jobs,
+                ..Default::default()
+            };
+            let records = move_files(
+                [
+                    ("a", "moved-a"),
+                    ("missing", "moved-b"),
+                    ("c", "moved-c"),
+                    ("d", "moved-d"),
+                ]
+                .map(|(source, destination)| (path(source), path(destination)))
+                .to_vec(),
+                &options,
+                None,
+                &mut QuietReporter,
+            );
+            assert_eq!(records[0].status, MoveStatus::Done, "{jobs}");
+            assert!(matches!(records[1].status, MoveStatus::Failed(_)), "{jobs}");
+            if jobs == 1 {
+                assert_eq!(records[2].status, MoveStatus::Cancelled);
+                assert!(path("c").exists());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test_backup {
+    use super::{backup_path, move_file, ConflictPolicy, ExecutionOptions, MoveStatus};
+    use tempdir::TempDir;
+
+    #[test]
+    fn backup_path_is_free() -> anyhow::Result<()> {
+        let directory = TempDir::new("mmv-backup")?;
+        let destination = directory.path().join("file");
+        assert_eq!(
+            backup_path(&destination, "~"),
+            directory.path().join("file~")
+        );
+        std::fs::write(directory.path().join("file~"), "")?;
+        std::fs::write(directory.path().join("file.~1~"), "")?;
+        assert_eq!(
+            backup_path(&destination, "~"),
+            directory.path().join("file.~2~")
+        );
+        assert_eq!(
+            backup_path(&destination, ".bak"),
+            directory.path().join("file.bak")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn failed_move_restores_backup() -> anyhow::Result<()> {
+        let directory = TempDir::new("mmv-backup")?;
+        let destination = directory.path().join("file");
+        std::fs::write(&destination, "old")?;
+        let options = ExecutionOptions {
+            on_conflict: ConflictPolicy::Overwrite,
+            backup_suffix: Some("~".to_string()),
+            ..Default::default()
+        };
+        let record = move_file(
+            directory.path().join("missing"),
+            destination.clone(),
+            &options,
+        );
+        assert!(matches!(record.status, MoveStatus::Failed(_)));
+        assert_eq!(record.backup, None);
+        assert_eq!(std::fs::read_to_string(&destination)?, "old");
+        assert!(!directory.path().join("file~").exists());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test_on_conflict {
+    use super::{
+        move_file, move_files, numbered_path, ConflictPolicy, ExecutionOptions, MoveStatus,
+    };
+    use crate::output::QuietReporter;
+    use tempdir::TempDir;
+
+    #[test]
+    fn numbered_path_is_free() -> anyhow::Result<()> {
+        let directory = TempDir::new("mmv-on-conflict")?;
+        let destination = directory.path().join("foo.txt");
+        assert_eq!(
+            numbered_path(&destination),
+            directory.path().join("foo-1.txt")
+        );
+        std::fs::write(directory.path().join("foo-1.txt"), "")?;
+        assert_eq!(
+            numbered_path(&destination),
+            directory.path().join("foo-2.txt")
+        );
+        assert_eq!(
+            numbered_path(&directory.path().join("foo")),
+            directory.path().join("foo-1")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn policies() -> anyhow::Result<()> {
+        for (policy, expected_status, expected_destination) in [
+            (ConflictPolicy::Skip, MoveStatus::Skip, "old"),
+            (ConflictPolicy::Overwrite, MoveStatus::Done, "new"),
+            (ConflictPolicy::Rename, MoveStatus::Done, "old"),
+            (
+                ConflictPolicy::Fail,
+                MoveStatus::Failed("Destination already exists".to_owned()),
+                "old",
+            ),
+        ] {
+            let directory = TempDir::new("mmv-on-conflict")?;
+            let source = directory.path().join("source");
+            let destination = directory.path().join("foo");
+            std::fs::write(&source, "new")?;
+            std::fs::write(&destination, "old")?;
+            let options = ExecutionOptions {
+                on_conflict: policy,
+                ..Default::default()
+            };
+            let record = move_file(source.clone(), destination.clone(), &options);
+            assert_eq!(record.status, expected_status, "{policy:?}");
+            assert_eq!(
+                std::fs::read_to_string(&destination)?,
+                expected_destination,
+                "{policy:?}"
+            );
+            if policy == ConflictPolicy::Rename {
+                assert_eq!(record.destination, directory.path().join("foo-1"));
+                assert_eq!(std::fs::read_to_string(&record.destination)?, "new");
+            }
+        }
+        Ok(())
+    }
+    #[test]
+    fn parallel_renames_take_different_names() -> anyhow::Result<()> {
+        let directory = TempDir::new("mmv-on-conflict")?;
+        let path = |name: &str| directory.path().join(name);
+        for name in ["a", "b", "c", "foo", "bar"] {
+            std::fs::write(path(name), name)?;
+        }
+        // `foo-1` is free, but another move is going to take it
+        let moves = vec![
+            (path("a"), path("foo")),
+            (path("b"), path("foo-1")),
+            (path("c"), path("bar")),
+        ];
+        for dry_run in [true, false] {
+            let options = ExecutionOptions {
+                on_conflict: ConflictPolicy::Rename,
+                jobs: 3,
+                dry_run,
+                ..Default::default()
+            };
+            let records = move_files(moves.clone(), &options, None, &mut QuietReporter);
+            let destinations = records
+                .iter()
+                .map(|record| record.destination.clone())
+                .collect::<Vec<_>>();
+            assert_eq!(destinations, [path("foo-2"), path("foo-1"), path("bar-1")]);
+        }
+        for (name, content) in [
+            ("foo-2", "a"),
+            ("foo-1", "b"),
+            ("bar-1", "c"),
+            ("foo", "foo"),
+        ] {
+            assert_eq!(std::fs::read_to_string(path(name))?, content);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test_move_files {
+    use super::{move_files, ExecutionOptions, MoveRecord};
+    use crate::output::Reporter;
+    use std::path::PathBuf;
+    use tempdir::TempDir;
+
+    /// Remembers sources of the reported moves
+    #[derive(Default)]
+    struct RecordingReporter {
+        performed: Vec<PathBuf>,
+        reported: Vec<PathBuf>,
+    }
+
+    impl Reporter for RecordingReporter {
+        fn report(&mut self, record: &MoveRecord) {
+            self.reported.push(record.source.clone());
+        }
+
+        fn performed(&mut self, record: &MoveRecord) {
+            self.performed.push(record.source.clone());
+        }
+    }
+
+    #[test]
+    fn reporting() -> anyhow::Result<()> {
+        let directory = TempDir::new("mmv-reporting")?;
+        let sources = (0..20)
+            .map(|index| directory.path().join(index.to_string()))
+            .collect::<Vec<_>>();
+        for source in &sources {
+            std::fs::write(source, "")?;
+        }
+        for jobs in [1, 4] {
+            let options = ExecutionOptions {
+                jobs,
+                dry_run: true,
+                ..Default::default()
+            };
+            let moves = sources
+                .iter()
+                .map(|source| (source.clone(), source.with_extension("moved")))
+                .collect();
+            let mut reporter = RecordingReporter::default();
+            move_files(moves, &options, None, &mut reporter);
+            assert_eq!(reporter.reported, sources);
+            reporter.performed.sort();
+            let mut sorted_sources = sources.clone();
+            sorted_sources.sort();
+            assert_eq!(reporter.performed, sorted_sources);
+        }
+        Ok(())
+    }
+}
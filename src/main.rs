@@ -1,94 +1,785 @@
-#![feature(pattern)]
+mod confirmation;
+mod execution;
+mod output;
+mod sort;
+mod undo_log;
 
-use std::process::{self, ExitCode};
+use std::{
+    collections::HashSet,
+    ffi::OsStr,
+    hash::{DefaultHasher, Hash, Hasher},
+    io::{BufRead, IsTerminal},
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    process::{self, ExitCode},
+    str::FromStr,
+    time::Instant,
+};
 
 use anyhow::{bail, Context};
-use clap::Parser;
-use color_print::{self, cformat, cprint, cprintln};
+use clap::{error::ErrorKind, ArgAction, CommandFactory, Parser};
+use color_print::{self, cformat};
 
-use mmv_lib::{DestinationPathTemplate, SourcePathPattern};
+use confirmation::confirm;
+use execution::{
+    find_conflict, move_files, move_files_iter, roll_back, ConflictPolicy, ExecutionOptions,
+    MoveRecord, MoveStatus,
+};
+use mmv_lib::{
+    find_collisions, plan_moves_from_paths, plan_moves_iter, DestinationPathTemplate,
+    EntryDecision, GlobStarPattern, MatchOptions, MatchingMode, PlannedEntry, SourcePathPattern,
+};
+use output::{
+    format_bytes, write_json, FailuresReporter, HumanReporter, OutputFormat, ProgressReporter,
+    QuietReporter, Reporter, Summary,
+};
+use sort::{sort_moves, SortOrder};
+use undo_log::{read_undo_log, UndoLogWriter};
+
+/// Exit code used if some moves have failed
+const EXIT_FAILED: u8 = 1;
+/// Exit code used if some files have been skipped and `--error-on-skip` is given
+const EXIT_SKIPPED: u8 = 2;
 
 /// multi-mv: rename multiple files matching a pattern
 #[derive(Parser, Debug)]
-#[command(author, version)]
+#[command(
+    author,
+    version,
+    after_help = "Exit codes: 0 if all the files are moved, 1 if some moves have failed or files are busy, \
+    2 if some files are skipped and --error-on-skip is given."
+)]
 pub struct CLIArgs {
-    /// Source pattern. '*' matches any number of any characters.
-    source_pattern: SourcePathPattern,
+    /// Source pattern. '*' matches any number of any characters, '{a,b}' matches either 'a' or 'b'.
+    /// The last directory can be '**' matching all the subdirectories, a path of the subdirectory
+    /// is captured after the filename wildcards.
+    #[arg(required_unless_present = "undo")]
+    source_pattern: Option<String>,
+
+    #[arg(
+        required_unless_present = "undo",
+        help = cformat!(
+            "Destination template. \
+            Markers in format of <green>#NUM</> are replaced by characters matched \
+            by a corresponding, i.e. <green>NUM</>th, wildcard. \
+            <green>#0</> is replaced by the whole source filename. \
+            A template ending with '/' moves files into the directory keeping their names. \
+            <green>$VAR</> and <green>${{VAR}}</> are replaced by environment variables, \
+            use <green>$$</> for a literal '$'."
+        )
+    )]
+    destination_template: Option<String>,
 
-    #[arg(help = cformat!(
-        "Destination template. \
-        Markers in format of <green>#NUM</> are replaced by characters matched \
-        by a corresponding, i.e. <green>NUM</>th, wildcard.")
+    /// Another pair of a source pattern and a destination template. Can be given several times.
+    /// All the pairs are checked not to move different files to the same destination before
+    /// any file is moved.
+    #[arg(
+        long,
+        num_args = 2,
+        value_names = ["SOURCE_PATTERN", "DESTINATION_TEMPLATE"],
+        action = ArgAction::Append,
+        conflicts_with = "from_stdin"
     )]
-    destination_template: String,
+    also: Vec<String>,
 
-    /// Replace existing files
+    /// Replace existing files, the same as --on-conflict overwrite
     #[arg(short, long)]
     force: bool,
-}
 
-fn main() -> anyhow::Result<process::ExitCode> {
-    let cli_args = CLIArgs::parse();
+    /// What to do if a destination already exists
+    #[arg(
+        long,
+        value_enum,
+        default_value_t,
+        value_name = "POLICY",
+        conflicts_with = "force"
+    )]
+    on_conflict: ConflictPolicy,
+
+    /// Copy files instead of moving them
+    #[arg(short, long, conflicts_with_all = ["undo_log", "undo", "atomic"])]
+    copy: bool,
+
+    /// Set access and modification times of copies to the ones of the original files
+    #[arg(short, long, requires = "copy")]
+    preserve_timestamps: bool,
+
+    /// Number of times a move is retried if the file is in use by another process,
+    /// with growing delays between the attempts
+    #[arg(long, default_value_t = 0, value_name = "N")]
+    retries: usize,
+
+    /// Exit with code 2 if some files are skipped because their destinations already exist
+    #[arg(long)]
+    error_on_skip: bool,
+
+    /// Move replaced files aside instead of overwriting them, requires --force or
+    /// --on-conflict overwrite
+    #[arg(long)]
+    backup: bool,
+
+    /// Suffix appended to backups. If such a backup already exists, a numbered backup
+    /// FILE.~N~ is made.
+    #[arg(long, default_value = "~", requires = "backup")]
+    backup_suffix: String,
+
+    /// Fail if the destination template refers to a wildcard which doesn't exist
+    /// instead of treating such marker as literal text
+    #[arg(long)]
+    strict: bool,
+
+    /// Match directories as well as files
+    #[arg(short, long)]
+    dirs: bool,
+
+    /// Match symlinks by their targets, e.g. skip symlinks to directories unless --dirs is given.
+    /// By default symlinks are matched and moved as links themselves, even dangling ones.
+    #[arg(short = 'L', long)]
+    follow_symlinks: bool,
+
+    /// Match hidden files, i.e. ones whose names begin with '.', and look into hidden
+    /// subdirectories of '**'. Otherwise hidden files are matched only if the filename part
+    /// of the source pattern begins with '.'.
+    #[arg(short = 'a', long, visible_alias = "all")]
+    hidden: bool,
+
+    /// Maximal depth of subdirectories '**' descends into: 0 means the directory before '**'
+    /// only, 1 means its subdirectories as well, and so on
+    #[arg(long, value_name = "N")]
+    max_depth: Option<usize>,
+
+    /// If a filename can be matched in several ways, make leading wildcards capture
+    /// as much as possible instead of as little as possible
+    #[arg(long, conflicts_with = "regex")]
+    greedy: bool,
+
+    /// Skip files whose names match the pattern. Can be given several times.
+    #[arg(long, value_name = "PATTERN")]
+    exclude: Vec<String>,
+
+    /// Move paths listed in stdin, one per line, instead of files in the directory of
+    /// the source pattern. Only filenames are matched against the pattern.
+    #[arg(long, conflicts_with_all = ["dirs", "follow_symlinks", "hidden", "max_depth"])]
+    from_stdin: bool,
+
+    /// Paths in stdin are separated by NUL characters instead of newlines
+    #[arg(short = '0', long, requires = "from_stdin")]
+    null: bool,
+
+    /// Match the source pattern against paths of files rather than filenames, so wildcards can
+    /// appear in directories as well, e.g. 'a*/*.txt'. '*' doesn't match '/'.
+    #[arg(long, conflicts_with_all = ["regex", "from_stdin"])]
+    match_path: bool,
+
+    /// Treat the filename part of the source pattern as a regular expression.
+    /// Markers refer to its capture groups.
+    #[arg(short, long)]
+    regex: bool,
+
+    /// If some move fails, don't start the remaining moves and move back the files which have
+    /// already been moved, restoring backups as well. It's a best effort: a file whose original
+    /// path has been taken meanwhile is left at its destination.
+    #[arg(long, conflicts_with = "undo_log")]
+    atomic: bool,
+
+    /// Record performed moves to a file which can be passed to --undo later
+    #[arg(long, value_name = "PATH")]
+    undo_log: Option<PathBuf>,
+
+    /// Reverse moves recorded by --undo-log instead of moving files matching a pattern
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["source_pattern", "destination_template", "dirs", "follow_symlinks", "hidden", "max_depth", "greedy", "match_path", "regex", "from_stdin", "exclude", "also"])]
+    undo: Option<PathBuf>,
+
+    /// Report every entry of the source directory and whether it's matched
+    #[arg(short, long, conflicts_with = "from_stdin")]
+    verbose: bool,
+
+    /// Print only failed moves, to stderr, and no summary
+    #[arg(short, long, conflicts_with_all = ["verbose", "progress"])]
+    quiet: bool,
+
+    /// Don't ask for confirmation before moving many files
+    #[arg(short, long)]
+    yes: bool,
 
-    let compiled_destination_pattern = DestinationPathTemplate::compile(
-        cli_args.destination_template.as_str(),
-        cli_args
-            .source_pattern
+    /// Number of moves starting from which confirmation is asked. Confirmation is asked only
+    /// if stdin is a terminal, otherwise files are moved without it.
+    #[arg(long, default_value_t = 100, value_name = "N")]
+    threshold: usize,
+
+    /// Only report what would be done without moving any files
+    #[arg(short = 'n', long)]
+    dry_run: bool,
+
+    /// Order in which files are moved
+    #[arg(long, value_enum, default_value_t)]
+    sort: SortOrder,
+
+    /// Number of threads moving files. Files are moved in an arbitrary order if it's
+    /// greater than one.
+    #[arg(short, long, default_value_t = NonZeroUsize::MIN, value_name = "N")]
+    jobs: NonZeroUsize,
+
+    /// Show a progress bar instead of a line per file when moving many files
+    #[arg(long)]
+    progress: bool,
+
+    /// Report the total size of the moved files and how long moving has taken
+    #[arg(long)]
+    stats: bool,
+
+    /// Output format
+    #[arg(long, value_enum, default_value_t)]
+    format: OutputFormat,
+}
+
+/// Compiles the source pattern and the destination template given in the command line
+fn compile_patterns<'a>(
+    source_pattern: &str,
+    destination_template: &'a str,
+    cli_args: &CLIArgs,
+) -> anyhow::Result<(SourcePathPattern, DestinationPathTemplate<'a>)> {
+    let parse_glob: fn(&str) -> Result<SourcePathPattern, &'static str> = if cli_args.match_path {
+        SourcePathPattern::from_path_glob
+    } else {
+        SourcePathPattern::from_str
+    };
+    let source_pattern = if cli_args.regex {
+        SourcePathPattern::from_regex(source_pattern)?
+    } else {
+        parse_glob(source_pattern)
+            .map_err(anyhow::Error::msg)?
+            .with_matching_mode(if cli_args.greedy {
+                MatchingMode::Greedy
+            } else {
+                MatchingMode::Lazy
+            })
+    };
+    let compiled_destination_pattern = DestinationPathTemplate::compile_with_variables(
+        destination_template,
+        source_pattern
             .wildcards_number()
             .try_into()
             .context("Too many wildcards: number of wildcards must be between 0 and 255")?,
-    );
+        |name| std::env::var(name).ok(),
+    )?;
+    let max_referenced_index = compiled_destination_pattern.max_referenced_index();
+    if cli_args.strict && max_referenced_index > source_pattern.wildcards_number() {
+        bail!(
+            "Destination template refers to wildcard #{}, \
+            but source pattern has only {} wildcards",
+            max_referenced_index,
+            source_pattern.wildcards_number()
+        );
+    }
+    Ok((source_pattern, compiled_destination_pattern))
+}
 
-    let calculated_source_destination = cli_args
-        .source_pattern
-        .matching_files(std::env::current_dir()?.as_path())?
-        .into_iter()
-        .map(|(source_path, flagments_to_substitute)| {
-            (
-                source_path,
-                compiled_destination_pattern.substitute(
-                    &flagments_to_substitute
-                        .iter()
-                        .map(|string| string.as_str())
-                        .collect::<Vec<_>>(),
-                ),
-            )
-        })
-        .collect::<Vec<_>>();
+/// Whether the source path is excluded by an `--exclude` pattern
+fn is_excluded(exclude_patterns: &[GlobStarPattern], source_path: &Path) -> bool {
+    let source_filename = source_path
+        .file_name()
+        .and_then(OsStr::to_str)
+        .unwrap_or_default();
+    exclude_patterns
+        .iter()
+        .any(|pattern| pattern.match_string(source_filename).is_some())
+}
+
+fn match_options(cli_args: &CLIArgs) -> MatchOptions {
+    MatchOptions {
+        include_directories: cli_args.dirs,
+        follow_symlinks: cli_args.follow_symlinks,
+        include_hidden: cli_args.hidden,
+        max_depth: cli_args.max_depth,
+    }
+}
+
+/// Lists the source directory calling `on_move` for each move which isn't excluded. Entries
+/// are logged while the directory is listed if `--verbose` is given, so excluded ones are never
+/// collected. Returns paths of the entries whose names aren't valid UTF-8.
+fn walk_moves(
+    source_pattern: &SourcePathPattern,
+    destination_template: &DestinationPathTemplate,
+    exclude_patterns: &[GlobStarPattern],
+    cli_args: &CLIArgs,
+    mut on_move: impl FnMut(PathBuf, PathBuf),
+) -> anyhow::Result<Vec<PathBuf>> {
+    let log = |path: &Path, reason: &str| {
+        if cli_args.verbose {
+            eprintln!("{path:#?}: {reason}");
+        }
+    };
+    let matched = cformat!("<green>matched</>");
+    let mut non_utf8 = vec![];
+    for entry in plan_moves_iter(
+        source_pattern,
+        destination_template,
+        &std::env::current_dir()?,
+        &match_options(cli_args),
+    ) {
+        match entry? {
+            PlannedEntry::Move(source_path, _) if is_excluded(exclude_patterns, &source_path) => {
+                log(&source_path, "skipped, matches an --exclude pattern")
+            }
+            PlannedEntry::Move(source_path, destination_path) => {
+                log(&source_path, &matched);
+                on_move(source_path, destination_path);
+            }
+            PlannedEntry::NonUtf8(path) => {
+                log(&path, &decision_reason(&EntryDecision::NonUtf8));
+                non_utf8.push(path);
+            }
+            PlannedEntry::Skipped(path, decision) => log(&path, &decision_reason(&decision)),
+        }
+    }
+    Ok(non_utf8)
+}
+
+fn warn_non_utf8(non_utf8: &[PathBuf]) {
+    for path in non_utf8 {
+        eprintln!(
+            "{}",
+            cformat!("<yellow>Warning</>: skipping {path:#?}: filename is not valid UTF-8")
+        );
+    }
+    if !non_utf8.is_empty() {
+        eprintln!(
+            "Entries skipped because of non-UTF-8 names: {}",
+            non_utf8.len()
+        );
+    }
+}
+
+/// Fails if the directory of the destination template doesn't exist
+fn check_target_directory(destination_template: &DestinationPathTemplate) -> anyhow::Result<()> {
+    let target_directory = &destination_template.directory;
+    if !target_directory.as_os_str().is_empty() {
+        // Without the trailing slash, so that a file is found rather than reported missing
+        let target_path = target_directory.components().as_path();
+        match target_path.metadata() {
+            Ok(metadata) if !metadata.is_dir() => {
+                bail!("Target path {target_path:#?} exists but is not a directory")
+            }
+            Ok(_) => {}
+            Err(_) => bail!("Target directory {target_directory:#?} doesn't exist"),
+        }
+    }
+    Ok(())
+}
+
+/// Calculates the list of `(source, destination)` pairs for files matching `source_pattern`
+fn plan_moves(
+    source_pattern: &str,
+    destination_template: &str,
+    cli_args: &CLIArgs,
+) -> anyhow::Result<Vec<(PathBuf, PathBuf)>> {
+    let (source_pattern, compiled_destination_pattern) =
+        compile_patterns(source_pattern, destination_template, cli_args)?;
+    let exclude_patterns = exclude_patterns(cli_args);
+    let mut calculated_source_destination = vec![];
+    let non_utf8 = if cli_args.from_stdin {
+        let planned_moves = plan_moves_from_paths(
+            &source_pattern,
+            &compiled_destination_pattern,
+            read_paths(
+                std::io::stdin().lock(),
+                if cli_args.null { b'\0' } else { b'\n' },
+            )?,
+        );
+        calculated_source_destination = planned_moves.moves;
+        calculated_source_destination
+            .retain(|(source_path, _)| !is_excluded(&exclude_patterns, source_path));
+        planned_moves.non_utf8
+    } else {
+        walk_moves(
+            &source_pattern,
+            &compiled_destination_pattern,
+            &exclude_patterns,
+            cli_args,
+            |source_path, destination_path| {
+                calculated_source_destination.push((source_path, destination_path))
+            },
+        )?
+    };
+    warn_non_utf8(&non_utf8);
+    sort_moves(&mut calculated_source_destination, cli_args.sort);
 
     if calculated_source_destination.is_empty() {
         bail!(cformat!(
             "No files matching pattern <green>{}</>",
-            cli_args.source_pattern
+            source_pattern
         ));
     }
+    check_target_directory(&compiled_destination_pattern)?;
+    Ok(calculated_source_destination)
+}
 
-    if !compiled_destination_pattern
-        .directory
-        .as_os_str()
-        .is_empty()
-        && !compiled_destination_pattern.directory.exists()
-    {
-        bail!(
-            "Target directory {:#?} doesn't exist",
-            compiled_destination_pattern.directory
+fn exclude_patterns(cli_args: &CLIArgs) -> Vec<GlobStarPattern> {
+    cli_args
+        .exclude
+        .iter()
+        .map(|pattern| GlobStarPattern::from(pattern.as_str()))
+        .collect()
+}
+
+/// Whether the moves can be performed while the source directory is being listed instead of
+/// being collected first, see [`stream_moves`]. They can't be if they are sorted, checked for
+/// conflicts all together, performed in parallel, rolled back, reported as JSON or measured.
+fn can_stream(cli_args: &CLIArgs, on_conflict: ConflictPolicy) -> bool {
+    cli_args.sort == SortOrder::None
+        && cli_args.also.is_empty()
+        && !cli_args.from_stdin
+        && !cli_args.verbose
+        && cli_args.jobs.get() == 1
+        && matches!(
+            on_conflict,
+            ConflictPolicy::Skip | ConflictPolicy::Overwrite
+        )
+        && !cli_args.atomic
+        && cli_args.format == OutputFormat::Human
+        && !cli_args.stats
+}
+
+/// Performs the moves while the source directory is being listed, so that they are never
+/// collected. The directory is listed twice: the first pass checks that no destinations
+/// collide keeping only their hashes, the second one performs the moves. Entries which
+/// appear between the passes aren't moved.
+///
+/// Returns `None` without moving anything if the moves have to be collected after all: some
+/// destinations may collide, or the target directory is inside the listed one, so the moved
+/// files could be listed again. Errors are reported by [`plan_moves`] in this case as well.
+fn stream_moves(
+    source_pattern: &str,
+    destination_template: &str,
+    cli_args: &CLIArgs,
+    execution_options: &ExecutionOptions,
+) -> anyhow::Result<Option<Summary>> {
+    let (source_pattern, compiled_destination_pattern) =
+        compile_patterns(source_pattern, destination_template, cli_args)?;
+    // Both passes must give the same destinations
+    let compiled_destination_pattern = compiled_destination_pattern.at_current_time();
+    let working_directory = std::env::current_dir()?;
+    let (Ok(source_directory), Ok(target_directory)) = (
+        working_directory
+            .join(source_pattern.directory())
+            .canonicalize(),
+        working_directory
+            .join(&compiled_destination_pattern.directory)
+            .canonicalize(),
+    ) else {
+        return Ok(None);
+    };
+    if !target_directory.is_dir() || target_directory.starts_with(&source_directory) {
+        return Ok(None);
+    }
+
+    let exclude_patterns = exclude_patterns(cli_args);
+    let mut destination_hashes = HashSet::new();
+    let mut may_collide = false;
+    let non_utf8 = walk_moves(
+        &source_pattern,
+        &compiled_destination_pattern,
+        &exclude_patterns,
+        cli_args,
+        |_, destination_path| may_collide |= !destination_hashes.insert(hash(&destination_path)),
+    )?;
+    if may_collide || destination_hashes.is_empty() {
+        return Ok(None);
+    }
+    warn_non_utf8(&non_utf8);
+    confirm_moves(cli_args, destination_hashes.len())?;
+
+    let mut undo_log = create_undo_log(cli_args)?;
+    let mut reporter = create_reporter(cli_args, destination_hashes.len());
+    let mut listing_error = None;
+    let moves = plan_moves_iter(
+        &source_pattern,
+        &compiled_destination_pattern,
+        &working_directory,
+        &match_options(cli_args),
+    )
+    .map_while(|entry| entry.map_err(|error| listing_error = Some(error)).ok())
+    .filter_map(|entry| match entry {
+        PlannedEntry::Move(source_path, destination_path) => Some((source_path, destination_path)),
+        _ => None,
+    })
+    .filter(|(source_path, destination_path)| {
+        // Each destination found by the first pass is taken only once
+        !is_excluded(&exclude_patterns, source_path)
+            && destination_hashes.remove(&hash(destination_path))
+    });
+    let summary = move_files_iter(
+        moves,
+        execution_options,
+        undo_log.as_mut(),
+        reporter.as_mut(),
+    );
+    reporter.finish();
+    if let Some(error) = listing_error {
+        if !cli_args.quiet {
+            eprintln!("{summary}");
+        }
+        return Err(error);
+    }
+    Ok(Some(summary))
+}
+
+fn hash(path: &Path) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    path.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Returns a reason why the entry is matched or not
+fn decision_reason(decision: &EntryDecision) -> String {
+    match decision {
+        EntryDecision::Matched(_) => cformat!("<green>matched</>"),
+        EntryDecision::NotMatched => "doesn't match the pattern".to_string(),
+        EntryDecision::Directory => "is a directory, use --dirs to match directories".to_string(),
+        EntryDecision::DanglingSymlink => "is a dangling symlink".to_string(),
+        EntryDecision::SpecialFile => "is not a regular file".to_string(),
+        EntryDecision::Hidden => "is hidden, use --hidden to match hidden files".to_string(),
+        EntryDecision::NonUtf8 => "filename is not valid UTF-8".to_string(),
+    }
+}
+
+/// Reports the reversing moves which haven't been done by [`roll_back`]
+fn report_roll_back(failed_records: &[MoveRecord]) {
+    for record in failed_records {
+        let reason = match &record.status {
+            MoveStatus::Failed(error) | MoveStatus::Busy(error) => error.as_str(),
+            _ => "the path is taken",
+        };
+        eprintln!(
+            "{}",
+            cformat!(
+                "<red>Failed to move {:#?} back to {:#?}</>: {}",
+                record.source,
+                record.destination,
+                reason
+            )
         );
     }
+    if failed_records.is_empty() {
+        eprintln!("All the moved files are moved back");
+    } else {
+        eprintln!("Some of the moved files couldn't be moved back");
+    }
+}
+
+/// Fails if several files are to be moved to the same destination
+fn check_collisions(moves: &[(PathBuf, PathBuf)]) -> anyhow::Result<()> {
+    let Some((destination, sources)) = find_collisions(moves).into_iter().next() else {
+        return Ok(());
+    };
+    let mut sources = sources
+        .iter()
+        .map(|source| format!("{source:#?}"))
+        .collect::<Vec<_>>();
+    let last_source = sources.pop().expect("a collision has several sources");
+    bail!(
+        "Files {} and {} would {} be moved to {:#?}",
+        sources.join(", "),
+        last_source,
+        if sources.len() == 1 { "both" } else { "all" },
+        destination
+    );
+}
 
-    let mut failed_at_least_once = false;
-    for (source, destination) in calculated_source_destination {
-        cprint!("Moving <yellow>{source:?}</> -> <green>{destination:?}</>: ");
-        if !cli_args.force && destination.exists() {
-            cprintln!("<yellow>Skip</>: file already exists");
+/// Reads non-empty paths separated by `separator`
+fn read_paths(reader: impl BufRead, separator: u8) -> anyhow::Result<Vec<PathBuf>> {
+    let mut paths = vec![];
+    for path in reader.split(separator) {
+        let path = path.context("Failed to read paths")?;
+        if path.is_empty() {
             continue;
         }
-        if let Err(error) = std::fs::rename(source, destination) {
-            cprintln!("<red>Failed</>: {error:#}");
-            failed_at_least_once = true;
-        } else {
-            cprintln!("<green>Done</>");
+        match String::from_utf8(path) {
+            Ok(path) => paths.push(PathBuf::from(path)),
+            Err(error) => bail!(
+                "Path {:?} is not valid UTF-8",
+                String::from_utf8_lossy(error.as_bytes())
+            ),
+        }
+    }
+    Ok(paths)
+}
+
+fn main() -> anyhow::Result<process::ExitCode> {
+    let cli_args = CLIArgs::parse();
+    let on_conflict = if cli_args.force {
+        ConflictPolicy::Overwrite
+    } else {
+        cli_args.on_conflict
+    };
+    if cli_args.backup && on_conflict != ConflictPolicy::Overwrite {
+        CLIArgs::command()
+            .error(
+                ErrorKind::MissingRequiredArgument,
+                "--backup requires --force or --on-conflict overwrite",
+            )
+            .exit();
+    }
+
+    let execution_options = ExecutionOptions {
+        on_conflict,
+        backup_suffix: cli_args.backup.then_some(cli_args.backup_suffix.clone()),
+        dry_run: cli_args.dry_run,
+        jobs: cli_args.jobs.get(),
+        copy: cli_args.copy,
+        preserve_timestamps: cli_args.preserve_timestamps,
+        retries: cli_args.retries,
+        stop_on_failure: cli_args.atomic,
+    };
+    if let (Some(source_pattern), Some(destination_template), true) = (
+        &cli_args.source_pattern,
+        &cli_args.destination_template,
+        can_stream(&cli_args, on_conflict),
+    ) {
+        if let Some(summary) = stream_moves(
+            source_pattern,
+            destination_template,
+            &cli_args,
+            &execution_options,
+        )? {
+            if !cli_args.quiet {
+                eprintln!("{summary}");
+            }
+            return Ok(exit_code(
+                summary.failed + summary.busy > 0,
+                &summary,
+                &cli_args,
+            ));
+        }
+    }
+
+    let moves = match (
+        &cli_args.undo,
+        &cli_args.source_pattern,
+        &cli_args.destination_template,
+    ) {
+        (Some(undo_log_path), _, _) => read_undo_log(undo_log_path)?,
+        (None, Some(source_pattern), Some(destination_template)) => {
+            let mut moves = plan_moves(source_pattern, destination_template, &cli_args)?;
+            for pair in cli_args.also.chunks(2) {
+                moves.append(&mut plan_moves(&pair[0], &pair[1], &cli_args)?);
+            }
+            check_collisions(&moves)?;
+            moves
+        }
+        _ => unreachable!("clap ensures that either --undo or both patterns are given"),
+    };
+
+    if on_conflict == ConflictPolicy::Fail {
+        if let Some((source, destination)) = find_conflict(&moves) {
+            bail!("Destination {destination:#?} of {source:#?} already exists, no files are moved");
         }
     }
 
-    Ok(ExitCode::from(failed_at_least_once as u8))
+    confirm_moves(&cli_args, moves.len())?;
+    let mut undo_log = create_undo_log(&cli_args)?;
+    let mut reporter = create_reporter(&cli_args, moves.len());
+    let sizes = cli_args.stats.then(|| {
+        moves
+            .iter()
+            .map(|(source, _)| {
+                source
+                    .symlink_metadata()
+                    .map_or(0, |metadata| metadata.len())
+            })
+            .collect::<Vec<_>>()
+    });
+    let start_time = Instant::now();
+    let mut records = move_files(
+        moves,
+        &execution_options,
+        undo_log.as_mut(),
+        reporter.as_mut(),
+    );
+    let elapsed_time = start_time.elapsed();
+    reporter.finish();
+    let has_failed = records
+        .iter()
+        .any(|record| matches!(record.status, MoveStatus::Failed(_) | MoveStatus::Busy(_)));
+    // Rolled back before reporting, so that the moved back files aren't reported as moved
+    let failed_roll_back = (cli_args.atomic && has_failed).then(|| roll_back(&mut records));
+    if cli_args.format == OutputFormat::Json {
+        write_json(&records, std::io::stdout().lock())?;
+    }
+
+    let summary = Summary {
+        copy: cli_args.copy,
+        ..Summary::new(&records)
+    };
+    if !cli_args.quiet {
+        eprintln!("{summary}");
+    }
+    if let Some(sizes) = sizes {
+        let moved_bytes = records
+            .iter()
+            .zip(sizes)
+            .filter(|(record, _)| record.status == MoveStatus::Done)
+            .map(|(_, size)| size)
+            .sum();
+        eprintln!(
+            "{} {} in {:.1}s",
+            if cli_args.copy { "copied" } else { "moved" },
+            format_bytes(moved_bytes),
+            elapsed_time.as_secs_f64()
+        );
+    }
+    if let Some(failed_roll_back) = failed_roll_back {
+        report_roll_back(&failed_roll_back);
+    }
+
+    Ok(exit_code(has_failed, &summary, &cli_args))
+}
+
+fn exit_code(has_failed: bool, summary: &Summary, cli_args: &CLIArgs) -> ExitCode {
+    if has_failed {
+        ExitCode::from(EXIT_FAILED)
+    } else if cli_args.error_on_skip && summary.skipped > 0 {
+        ExitCode::from(EXIT_SKIPPED)
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+/// Asks whether to proceed if many files are to be moved, see `--threshold`
+fn confirm_moves(cli_args: &CLIArgs, moves_number: usize) -> anyhow::Result<()> {
+    let asks_confirmation = !cli_args.yes
+        && !cli_args.dry_run
+        && moves_number > cli_args.threshold
+        && std::io::stdin().is_terminal();
+    if asks_confirmation
+        && !confirm(
+            &format!("About to move {moves_number} files, proceed?"),
+            std::io::stdin().lock(),
+            std::io::stderr(),
+        )?
+    {
+        bail!("Cancelled, no files are moved");
+    }
+    Ok(())
+}
+
+fn create_undo_log(cli_args: &CLIArgs) -> anyhow::Result<Option<UndoLogWriter>> {
+    cli_args
+        .undo_log
+        .as_deref()
+        .filter(|_| !cli_args.dry_run)
+        .map(UndoLogWriter::create)
+        .transpose()
+}
+
+fn create_reporter(cli_args: &CLIArgs, moves_number: usize) -> Box<dyn Reporter> {
+    match cli_args.format {
+        OutputFormat::Json => Box::new(QuietReporter),
+        OutputFormat::Human if cli_args.quiet => Box::new(FailuresReporter),
+        OutputFormat::Human if cli_args.progress && moves_number > ProgressReporter::THRESHOLD => {
+            Box::new(ProgressReporter::new(moves_number))
+        }
+        OutputFormat::Human => Box::new(HumanReporter),
+    }
 }
@@ -0,0 +1,273 @@
+use std::{fmt::Display, io::Write};
+
+use clap::ValueEnum;
+use color_print::cformat;
+use indicatif::{ProgressBar, ProgressStyle};
+use serde::Serialize;
+
+use crate::execution::{MoveRecord, MoveStatus};
+
+/// Format of the report about performed moves
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Colored line per move
+    #[default]
+    Human,
+    /// Single JSON array of objects with `source`, `destination`, `status`, `error` and `backup`
+    /// fields
+    Json,
+}
+
+/// Returns a colored line describing the move
+pub fn format_human(record: &MoveRecord) -> String {
+    let MoveRecord {
+        source,
+        destination,
+        status,
+        backup,
+    } = record;
+    let status = match (status, backup) {
+        (MoveStatus::Done, Some(backup)) => {
+            cformat!("<green>Done</>, replaced file is moved to <yellow>{backup:?}</>")
+        }
+        (MoveStatus::Done, None) => cformat!("<green>Done</>"),
+        (MoveStatus::Skip, _) => cformat!("<yellow>Skip</>: file already exists"),
+        (MoveStatus::Failed(error), _) => cformat!("<red>Failed</>: {error}"),
+        (MoveStatus::Busy(error), _) => cformat!("<red>Busy</>: {error}"),
+        (MoveStatus::Planned, _) => cformat!("<cyan>Planned</>"),
+        (MoveStatus::Unchanged, _) => {
+            cformat!("<cyan>Unchanged</>: source and destination are the same")
+        }
+        (MoveStatus::Cancelled, _) => cformat!("<yellow>Cancelled</>: another move has failed"),
+        (MoveStatus::RolledBack, _) => {
+            cformat!("<yellow>Rolled back</>: another move has failed")
+        }
+    };
+    cformat!("Moving <yellow>{source:?}</> -> <green>{destination:?}</>: {status}")
+}
+
+/// Formats a number of bytes using binary units, e.g. `1.5 KiB`. Sizes less than a KiB are
+/// written exactly, bigger ones are rounded to one decimal place.
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 6] = ["KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit_index = 0;
+    // Values rounded up to 1024.0 are written in the next unit
+    while value >= 1023.95 && unit_index + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit_index += 1;
+    }
+    format!("{value:.1} {}", UNITS[unit_index])
+}
+
+/// Numbers of moves with each status
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Summary {
+    pub done: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    pub busy: usize,
+    pub planned: usize,
+    pub unchanged: usize,
+    pub cancelled: usize,
+    pub rolled_back: usize,
+    /// Whether the files are copied rather than moved, only changes how the summary is shown
+    pub copy: bool,
+}
+
+impl Summary {
+    pub fn new<'a>(records: impl IntoIterator<Item = &'a MoveRecord>) -> Self {
+        let mut summary = Self::default();
+        for record in records {
+            summary.add(&record.status);
+        }
+        summary
+    }
+
+    pub fn add(&mut self, status: &MoveStatus) {
+        match status {
+            MoveStatus::Done => self.done += 1,
+            MoveStatus::Skip => self.skipped += 1,
+            MoveStatus::Failed(_) => self.failed += 1,
+            MoveStatus::Busy(_) => self.busy += 1,
+            MoveStatus::Planned => self.planned += 1,
+            MoveStatus::Unchanged => self.unchanged += 1,
+            MoveStatus::Cancelled => self.cancelled += 1,
+            MoveStatus::RolledBack => self.rolled_back += 1,
+        }
+    }
+}
+
+impl Display for Summary {
+    fn fmt(&self, format: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.planned > 0 {
+            write!(format, "{} planned, ", self.planned)?;
+        }
+        write!(
+            format,
+            "{} {}, {} skipped, {} failed",
+            self.done,
+            if self.copy { "copied" } else { "moved" },
+            self.skipped,
+            self.failed
+        )?;
+        if self.busy > 0 {
+            write!(format, ", {} busy", self.busy)?;
+        }
+        if self.unchanged > 0 {
+            write!(format, ", {} unchanged", self.unchanged)?;
+        }
+        if self.cancelled > 0 {
+            write!(format, ", {} cancelled", self.cancelled)?;
+        }
+        if self.rolled_back > 0 {
+            write!(format, ", {} rolled back", self.rolled_back)?;
+        }
+        Ok(())
+    }
+}
+
+/// Receives results of the moves as they are performed
+pub trait Reporter {
+    /// Called after each move, in the original order of the moves
+    fn report(&mut self, record: &MoveRecord);
+
+    /// Called as soon as a move is done. Unlike [`report`][Reporter::report], it's called in
+    /// arbitrary order when the moves are performed in parallel.
+    fn performed(&mut self, _record: &MoveRecord) {}
+
+    /// Called after all the moves
+    fn finish(&mut self) {}
+}
+
+/// Prints a colored line per move
+pub struct HumanReporter;
+
+impl Reporter for HumanReporter {
+    fn report(&mut self, record: &MoveRecord) {
+        println!("{}", format_human(record));
+    }
+}
+
+/// Prints nothing
+pub struct QuietReporter;
+
+impl Reporter for QuietReporter {
+    fn report(&mut self, _record: &MoveRecord) {}
+}
+
+/// Prints only failed moves, to stderr
+pub struct FailuresReporter;
+
+impl Reporter for FailuresReporter {
+    fn report(&mut self, record: &MoveRecord) {
+        if let MoveStatus::Failed(_) | MoveStatus::Busy(_) = record.status {
+            eprintln!("{}", format_human(record));
+        }
+    }
+}
+
+/// Shows a progress bar and prints only failed moves, to stderr
+pub struct ProgressReporter {
+    progress_bar: ProgressBar,
+}
+
+impl ProgressReporter {
+    /// The progress bar is worth showing only for more moves than this
+    pub const THRESHOLD: usize = 100;
+
+    pub fn new(moves_number: usize) -> Self {
+        let progress_bar = ProgressBar::new(moves_number as u64).with_style(
+            ProgressStyle::with_template("{bar:40} {pos}/{len} [{elapsed_precise}]")
+                .expect("the template is valid"),
+        );
+        Self { progress_bar }
+    }
+}
+
+impl Reporter for ProgressReporter {
+    fn report(&mut self, _record: &MoveRecord) {}
+
+    fn performed(&mut self, record: &MoveRecord) {
+        if let MoveStatus::Failed(_) | MoveStatus::Busy(_) = record.status {
+            self.progress_bar
+                .suspend(|| eprintln!("{}", format_human(record)));
+        }
+        self.progress_bar.inc(1);
+    }
+
+    fn finish(&mut self) {
+        self.progress_bar.finish_and_clear();
+    }
+}
+
+#[derive(Serialize)]
+struct JsonRecord<'a> {
+    source: String,
+    destination: String,
+    status: &'static str,
+    error: Option<&'a str>,
+    backup: Option<String>,
+}
+
+/// Writes all the records as a single JSON array
+pub fn write_json(records: &[MoveRecord], mut writer: impl Write) -> anyhow::Result<()> {
+    let json_records = records
+        .iter()
+        .map(|record| {
+            let (status, error) = match &record.status {
+                MoveStatus::Done => ("done", None),
+                MoveStatus::Skip => ("skip", None),
+                MoveStatus::Failed(error) => ("failed", Some(error.as_str())),
+                MoveStatus::Busy(error) => ("busy", Some(error.as_str())),
+                MoveStatus::Planned => ("planned", None),
+                MoveStatus::Unchanged => ("unchanged", None),
+                MoveStatus::Cancelled => ("cancelled", None),
+                MoveStatus::RolledBack => ("rolled_back", None),
+            };
+            JsonRecord {
+                source: record.source.to_string_lossy().into_owned(),
+                destination: record.destination.to_string_lossy().into_owned(),
+                status,
+                error,
+                backup: record
+                    .backup
+                    .as_ref()
+                    .map(|backup| backup.to_string_lossy().into_owned()),
+            }
+        })
+        .collect::<Vec<_>>();
+    serde_json::to_writer_pretty(&mut writer, &json_records)?;
+    writeln!(writer)?;
+    Ok(writer.flush()?)
+}
+
+#[cfg(test)]
+mod test_format_bytes {
+    use super::format_bytes;
+
+    #[test]
+    fn it_works() {
+        const KIB: u64 = 1024;
+        const MIB: u64 = 1024 * KIB;
+        const GIB: u64 = 1024 * MIB;
+        for (bytes, expected) in [
+            (0, "0 B"),
+            (1023, "1023 B"),
+            (KIB, "1.0 KiB"),
+            (KIB + KIB / 2, "1.5 KiB"),
+            (MIB - 1, "1.0 MiB"),
+            (MIB, "1.0 MiB"),
+            (GIB - 1, "1.0 GiB"),
+            (GIB, "1.0 GiB"),
+            (GIB + GIB / 5, "1.2 GiB"),
+            (1024 * GIB, "1.0 TiB"),
+            (u64::MAX, "16.0 EiB"),
+        ] {
+            assert_eq!(format_bytes(bytes), expected, "{bytes}");
+        }
+    }
+}
@@ -0,0 +1,102 @@
+use std::{cmp::Ordering, path::PathBuf};
+
+use clap::ValueEnum;
+
+/// Order in which the moves are performed
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SortOrder {
+    /// Sort by source path
+    #[default]
+    Name,
+    /// Sort by source path comparing digit sequences as numbers, e.g. 2.png goes before 10.png
+    Natural,
+    /// Keep the order in which the files are listed by the filesystem. The files are then moved
+    /// while the directory is listed if possible, without collecting them first
+    None,
+}
+
+/// Sorts `(source, destination)` pairs by source
+pub fn sort_moves(moves: &mut [(PathBuf, PathBuf)], order: SortOrder) {
+    match order {
+        SortOrder::Name => moves.sort_by(|(left, _), (right, _)| left.cmp(right)),
+        SortOrder::Natural => moves.sort_by(|(left, _), (right, _)| {
+            natural_cmp(&left.to_string_lossy(), &right.to_string_lossy())
+        }),
+        SortOrder::None => {}
+    }
+}
+
+/// Compares strings treating sequences of ASCII digits as numbers. If numbers are equal,
+/// the one with less leading zeros goes first.
+pub fn natural_cmp(left: &str, right: &str) -> Ordering {
+    let (mut left, mut right) = (left, right);
+    loop {
+        let (Some(left_symbol), Some(right_symbol)) = (left.chars().next(), right.chars().next())
+        else {
+            return left.len().cmp(&right.len());
+        };
+        if left_symbol.is_ascii_digit() && right_symbol.is_ascii_digit() {
+            let (left_number, left_rest) = split_digits(left);
+            let (right_number, right_rest) = split_digits(right);
+            let (left_significant, right_significant) = (
+                left_number.trim_start_matches('0'),
+                right_number.trim_start_matches('0'),
+            );
+            let ordering = left_significant
+                .len()
+                .cmp(&right_significant.len())
+                .then_with(|| left_significant.cmp(right_significant))
+                .then_with(|| left_number.len().cmp(&right_number.len()));
+            if ordering.is_ne() {
+                return ordering;
+            }
+            (left, right) = (left_rest, right_rest);
+        } else {
+            let ordering = left_symbol.cmp(&right_symbol);
+            if ordering.is_ne() {
+                return ordering;
+            }
+            (left, right) = (
+                &left[left_symbol.len_utf8()..],
+                &right[right_symbol.len_utf8()..],
+            );
+        }
+    }
+}
+
+fn split_digits(string: &str) -> (&str, &str) {
+    string.split_at(
+        string
+            .find(|symbol: char| !symbol.is_ascii_digit())
+            .unwrap_or(string.len()),
+    )
+}
+
+#[cfg(test)]
+mod test_natural_cmp {
+    use super::natural_cmp;
+    use std::cmp::Ordering;
+
+    #[test]
+    fn it_works() {
+        for (left, right, expected) in [
+            ("2.png", "10.png", Ordering::Less),
+            ("10.png", "2.png", Ordering::Greater),
+            ("10.png", "10.png", Ordering::Equal),
+            ("img2", "img10", Ordering::Less),
+            ("img", "img1", Ordering::Less),
+            ("a10b2", "a10b10", Ordering::Less),
+            ("07", "7", Ordering::Greater),
+            ("007", "8", Ordering::Less),
+            ("b1", "a2", Ordering::Greater),
+            ("", "", Ordering::Equal),
+            (
+                "99999999999999999999999",
+                "100000000000000000000000",
+                Ordering::Less,
+            ),
+        ] {
+            assert_eq!(natural_cmp(left, right), expected, "{left} vs {right}");
+        }
+    }
+}
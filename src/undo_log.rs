@@ -0,0 +1,84 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context};
+
+use crate::execution::MoveRecord;
+
+/// Log of performed moves which allows to reverse them. Each move is written as a
+/// `SOURCE<TAB>DESTINATION[<TAB>BACKUP]` line, so paths containing tabs or newlines cannot
+/// be logged.
+pub struct UndoLogWriter {
+    file: File,
+}
+
+impl UndoLogWriter {
+    /// Creates a new log, truncating `path` if it already exists
+    pub fn create(path: &Path) -> anyhow::Result<Self> {
+        Ok(Self {
+            file: File::create(path).context(format!("Failed to create undo log {path:#?}"))?,
+        })
+    }
+
+    /// Appends a performed move to the log. Relative paths are resolved against the current
+    /// directory, so the log can be used from any other directory.
+    pub fn record(&mut self, record: &MoveRecord) -> anyhow::Result<()> {
+        let current_directory = std::env::current_dir()?;
+        let mut fields = vec![];
+        for path in [&record.source, &record.destination]
+            .into_iter()
+            .chain(&record.backup)
+        {
+            let path = current_directory.join(path);
+            match path.to_str() {
+                Some(path_str) if !path_str.contains(['\t', '\n']) => {
+                    fields.push(path_str.to_string())
+                }
+                _ => return Err(anyhow!("Path {path:#?} cannot be written to undo log")),
+            }
+        }
+        writeln!(self.file, "{}", fields.join("\t")).context("Failed to write to undo log")
+    }
+}
+
+/// Reads moves recorded by [`UndoLogWriter`] and returns moves reversing them in reverse order,
+/// see [`reversing_moves`]
+pub fn read_undo_log(path: &Path) -> anyhow::Result<Vec<(PathBuf, PathBuf)>> {
+    let file = File::open(path).context(format!("Failed to open undo log {path:#?}"))?;
+    let mut undo_moves = vec![];
+    for (line_index, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.context("Failed to read undo log")?;
+        let reversed_moves = match line.split('\t').collect::<Vec<_>>()[..] {
+            [source, destination] => reversing_moves(source.as_ref(), destination.as_ref(), None),
+            [source, destination, backup] => {
+                reversing_moves(source.as_ref(), destination.as_ref(), Some(backup.as_ref()))
+            }
+            _ => {
+                return Err(anyhow!(
+                    "Malformed undo log {path:#?}: line {} is not in format \
+                    SOURCE<TAB>DESTINATION[<TAB>BACKUP]",
+                    line_index + 1
+                ))
+            }
+        };
+        undo_moves.push(reversed_moves);
+    }
+    Ok(undo_moves.into_iter().rev().flatten().collect())
+}
+
+/// Returns moves reversing a move from `source` to `destination`: the destination is moved back,
+/// and then the `backup` of the replaced file, if any, is moved back to the destination
+pub fn reversing_moves(
+    source: &Path,
+    destination: &Path,
+    backup: Option<&Path>,
+) -> Vec<(PathBuf, PathBuf)> {
+    let mut moves = vec![(destination.to_path_buf(), source.to_path_buf())];
+    if let Some(backup) = backup {
+        moves.push((backup.to_path_buf(), destination.to_path_buf()));
+    }
+    moves
+}
@@ -17,3 +17,15 @@ pub fn generate_files(
     }
     Ok(root_tmp_dir)
 }
+
+/// Removes ANSI color escape sequences
+pub fn strip_colors(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(escape_begin) = rest.find('\x1b') {
+        result += &rest[..escape_begin];
+        rest = &rest[escape_begin..];
+        rest = &rest[rest.find('m').map_or(rest.len(), |end| end + 1)..];
+    }
+    result + rest
+}
@@ -1,7 +1,7 @@
 mod common;
 use std::{fs::read_dir, iter::empty, path::PathBuf, process::Command};
 
-use common::generate_files;
+use common::{generate_files, strip_colors};
 
 use assert_cmd::prelude::*;
 use predicates::str::contains;
@@ -61,6 +61,32 @@ fn test_no_target_directory() -> anyhow::Result<()> {
     Ok(temporary_directory.close()?)
 }
 
+#[test]
+fn test_target_directory_is_file() -> anyhow::Result<()> {
+    let temporary_directory = generate_files(
+        ["Harry", "Ron", "hufflepuff"]
+            .map(PathBuf::from)
+            .into_iter(),
+        empty(),
+    )?;
+    let output = Command::cargo_bin("mmv")?
+        .current_dir(temporary_directory.path())
+        .args(["*r*", "hufflepuff/#1-#2"])
+        .output()?;
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = strip_colors(&String::from_utf8(output.stderr)?);
+    assert_eq!(
+        stderr.lines().next(),
+        Some("Error: Target path \"hufflepuff\" exists but is not a directory")
+    );
+    let mut names = read_dir(temporary_directory.path())?
+        .map(|entry| Ok(entry?.file_name().into_string().unwrap()))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    names.sort();
+    assert_eq!(names, ["Harry", "Ron", "hufflepuff"]);
+    Ok(temporary_directory.close()?)
+}
+
 #[test]
 fn test_no_matches_for_pattern() -> anyhow::Result<()> {
     let temporary_directory = generate_files(
@@ -134,3 +160,1317 @@ fn test_force_flag() -> anyhow::Result<()> {
     }
     Ok(temporary_directory.close()?)
 }
+
+#[test]
+fn test_move_directories() -> anyhow::Result<()> {
+    let temporary_directory = generate_files(
+        ["project-tardis/console", "project-tardis/rooms/library"]
+            .map(PathBuf::from)
+            .into_iter(),
+        [
+            PathBuf::from("project-torchwood/"),
+            PathBuf::from("archive/"),
+        ]
+        .into_iter(),
+    )?;
+    let mut mmv = Command::cargo_bin("mmv")?;
+    mmv.current_dir(temporary_directory.path())
+        .args(["project-*", "archive/project-#1"])
+        .assert()
+        .failure()
+        .stderr(contains("No files matching pattern"));
+
+    let mut mmv = Command::cargo_bin("mmv")?;
+    mmv.current_dir(temporary_directory.path())
+        .args(["--dirs", "project-*", "archive/project-#1"])
+        .assert()
+        .success()
+        .stdout(contains("Done").count(2));
+    let archive = temporary_directory.path().join("archive");
+    assert!(archive.join("project-tardis/console").is_file());
+    assert!(archive.join("project-tardis/rooms/library").is_file());
+    assert!(archive.join("project-torchwood").is_dir());
+    assert!(!temporary_directory.path().join("project-tardis").exists());
+    Ok(temporary_directory.close()?)
+}
+
+#[test]
+fn test_dirs_doesnt_overwrite_existing_directory() -> anyhow::Result<()> {
+    let temporary_directory = generate_files(
+        ["companions/rose", "archive/companions/donna"]
+            .map(PathBuf::from)
+            .into_iter(),
+        empty(),
+    )?;
+    let mut mmv = Command::cargo_bin("mmv")?;
+    mmv.current_dir(temporary_directory.path())
+        .args(["-d", "compan*", "archive/compan#1"])
+        .assert()
+        .success()
+        .stdout(contains("Skip").count(1));
+    assert!(temporary_directory.path().join("companions/rose").exists());
+    assert!(temporary_directory
+        .path()
+        .join("archive/companions/donna")
+        .exists());
+
+    let mut mmv = Command::cargo_bin("mmv")?;
+    mmv.current_dir(temporary_directory.path())
+        .args(["-d", "-f", "compan*", "archive/compan#1"])
+        .assert()
+        .code(1)
+        .stdout(contains("Failed").count(1));
+    assert!(temporary_directory.path().join("companions/rose").exists());
+    Ok(temporary_directory.close()?)
+}
+
+#[test]
+fn test_dirs_recursive() -> anyhow::Result<()> {
+    let temporary_directory = generate_files(
+        ["src/a/b/c.txt", "src/a/d.txt", "src/e.txt"]
+            .map(PathBuf::from)
+            .into_iter(),
+        [PathBuf::from("out")].into_iter(),
+    )?;
+    let output = Command::cargo_bin("mmv")?
+        .current_dir(temporary_directory.path())
+        .args(["--dirs", "src/**/*", "out/#1"])
+        .output()?;
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stderr)?,
+        "2 moved, 0 skipped, 0 failed\n"
+    );
+    for name in ["out/a/b/c.txt", "out/a/d.txt", "out/e.txt"] {
+        assert!(temporary_directory.path().join(name).exists(), "{name}");
+    }
+    Ok(temporary_directory.close()?)
+}
+
+#[test]
+fn test_undo_round_trip() -> anyhow::Result<()> {
+    let temporary_directory = generate_files(
+        ["Harry", "Ron", "Hermy", "Neville"]
+            .iter()
+            .map(|name| PathBuf::from("griffindor").join(name)),
+        [PathBuf::from("expelled/")].into_iter(),
+    )?;
+    let undo_log = temporary_directory.path().join("undo.log");
+    let mut mmv = Command::cargo_bin("mmv")?;
+    mmv.current_dir(temporary_directory.path())
+        .args(["griffindor/*", "expelled/#1-expelled", "--undo-log"])
+        .arg(&undo_log)
+        .assert()
+        .success()
+        .stdout(contains("Done").count(4));
+    assert_eq!(
+        read_dir(temporary_directory.path().join("griffindor"))?.count(),
+        0
+    );
+
+    let mut mmv = Command::cargo_bin("mmv")?;
+    mmv.current_dir(temporary_directory.path().join("expelled"))
+        .arg("--undo")
+        .arg(&undo_log)
+        .assert()
+        .success()
+        .stdout(contains("Done").count(4));
+    for name in ["Harry", "Ron", "Hermy", "Neville"] {
+        assert!(temporary_directory
+            .path()
+            .join("griffindor")
+            .join(name)
+            .exists());
+    }
+    assert_eq!(
+        read_dir(temporary_directory.path().join("expelled"))?.count(),
+        0
+    );
+    Ok(temporary_directory.close()?)
+}
+
+#[test]
+fn test_undo_doesnt_overwrite_existing_file() -> anyhow::Result<()> {
+    let temporary_directory = generate_files(
+        ["Harry", "Hermy"]
+            .iter()
+            .map(|name| PathBuf::from("griffindor").join(name)),
+        empty(),
+    )?;
+    let undo_log = temporary_directory.path().join("undo.log");
+    let mut mmv = Command::cargo_bin("mmv")?;
+    mmv.current_dir(temporary_directory.path())
+        .args(["griffindor/H*", "H#1", "--undo-log"])
+        .arg(&undo_log)
+        .assert()
+        .success()
+        .stdout(contains("Done").count(2));
+    std::fs::write(
+        temporary_directory.path().join("griffindor/Harry"),
+        "new Harry",
+    )?;
+
+    let mut mmv = Command::cargo_bin("mmv")?;
+    mmv.arg("--undo")
+        .arg(&undo_log)
+        .assert()
+        .success()
+        .stdout(contains("Skip").count(1))
+        .stdout(contains("Done").count(1));
+    assert!(temporary_directory.path().join("Harry").exists());
+    assert!(temporary_directory.path().join("griffindor/Hermy").exists());
+
+    let mut mmv = Command::cargo_bin("mmv")?;
+    mmv.args(["--force", "--undo"])
+        .arg(&undo_log)
+        .assert()
+        .code(1)
+        .stdout(contains("Done").count(1))
+        .stdout(contains("Failed").count(1));
+    assert!(!temporary_directory.path().join("Harry").exists());
+    assert_eq!(
+        std::fs::read_to_string(temporary_directory.path().join("griffindor/Harry"))?,
+        ""
+    );
+    Ok(temporary_directory.close()?)
+}
+
+#[test]
+fn test_json_dry_run() -> anyhow::Result<()> {
+    let temporary_directory = generate_files(
+        ["Harry", "Ron", "Hermy"]
+            .iter()
+            .map(|name| PathBuf::from("griffindor").join(name))
+            .chain([PathBuf::from("slytherin/Harry")]),
+        empty(),
+    )?;
+    let mut mmv = Command::cargo_bin("mmv")?;
+    let output = mmv
+        .current_dir(temporary_directory.path())
+        .args([
+            "--format",
+            "json",
+            "--dry-run",
+            "griffindor/H*",
+            "slytherin/H#1",
+        ])
+        .output()?;
+    assert!(output.status.success());
+
+    let mut records: Vec<serde_json::Value> = serde_json::from_slice(&output.stdout)?;
+    records.sort_by_key(|record| record["source"].as_str().map(str::to_string));
+    assert_eq!(
+        records,
+        [
+            serde_json::json!({
+                "source": "griffindor/Harry",
+                "destination": "slytherin/Harry",
+                "status": "skip",
+                "error": null,
+                "backup": null,
+            }),
+            serde_json::json!({
+                "source": "griffindor/Hermy",
+                "destination": "slytherin/Hermy",
+                "status": "planned",
+                "error": null,
+                "backup": null,
+            }),
+        ]
+    );
+    assert!(temporary_directory.path().join("griffindor/Hermy").exists());
+    assert!(!temporary_directory.path().join("slytherin/Hermy").exists());
+    Ok(temporary_directory.close()?)
+}
+
+#[test]
+fn test_json_reports_failures() -> anyhow::Result<()> {
+    let temporary_directory = generate_files(
+        ["companions/rose", "companions/martha", "archive/rose/notes"]
+            .map(PathBuf::from)
+            .into_iter(),
+        empty(),
+    )?;
+    let mut mmv = Command::cargo_bin("mmv")?;
+    let output = mmv
+        .current_dir(temporary_directory.path())
+        .args(["--format=json", "-f", "companions/*", "archive/#1"])
+        .output()?;
+    assert_eq!(output.status.code(), Some(1));
+    let mut records: Vec<serde_json::Value> = serde_json::from_slice(&output.stdout)?;
+    records.sort_by_key(|record| record["source"].as_str().map(str::to_string));
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0]["status"], "done");
+    assert!(records[0]["error"].is_null());
+    assert_eq!(records[1]["status"], "failed");
+    assert!(records[1]["error"].is_string());
+    Ok(temporary_directory.close()?)
+}
+
+#[test]
+fn test_regex() -> anyhow::Result<()> {
+    let temporary_directory = generate_files(
+        ["1-boot.log", "22-kernel.log", "kernel.log", "3-boot.txt"]
+            .iter()
+            .map(|name| PathBuf::from("logs").join(name)),
+        empty(),
+    )?;
+    let mut mmv = Command::cargo_bin("mmv")?;
+    mmv.current_dir(temporary_directory.path())
+        .args(["--regex", r"logs/(\d+)-(.*)\.log", "logs/#2_#1:03.log"])
+        .assert()
+        .success()
+        .stdout(contains("Done").count(2));
+    let logs = temporary_directory.path().join("logs");
+    assert!(logs.join("boot_001.log").exists());
+    assert!(logs.join("kernel_022.log").exists());
+    assert!(logs.join("kernel.log").exists());
+    assert!(logs.join("3-boot.txt").exists());
+    Ok(temporary_directory.close()?)
+}
+
+#[test]
+fn test_whole_filename_marker() -> anyhow::Result<()> {
+    let temporary_directory = generate_files(
+        ["original", "copy.txt"].map(PathBuf::from).into_iter(),
+        [PathBuf::from("backup")].into_iter(),
+    )?;
+    let mut mmv = Command::cargo_bin("mmv")?;
+    mmv.current_dir(temporary_directory.path())
+        .args(["*", "backup/#0.bak"])
+        .assert()
+        .success()
+        .stdout(contains("Done").count(2));
+    let backup = temporary_directory.path().join("backup");
+    assert!(backup.join("original.bak").exists());
+    assert!(backup.join("copy.txt.bak").exists());
+    Ok(temporary_directory.close()?)
+}
+
+#[test]
+fn test_backup() -> anyhow::Result<()> {
+    let temporary_directory = generate_files(empty(), empty())?;
+    let path = |name: &str| temporary_directory.path().join(name);
+    std::fs::write(path("new.txt"), "new")?;
+    std::fs::write(path("old.txt"), "old")?;
+    std::fs::write(path("old.txt~"), "older")?;
+    let undo_log = path("undo.log");
+
+    let mut mmv = Command::cargo_bin("mmv")?;
+    mmv.current_dir(temporary_directory.path())
+        .args(["-f", "--backup", "new.txt", "old.txt", "--undo-log"])
+        .arg(&undo_log)
+        .assert()
+        .success()
+        .stdout(contains("Done").count(1))
+        .stdout(contains("old.txt.~1~"));
+    assert!(!path("new.txt").exists());
+    assert_eq!(std::fs::read_to_string(path("old.txt"))?, "new");
+    assert_eq!(std::fs::read_to_string(path("old.txt.~1~"))?, "old");
+    assert_eq!(std::fs::read_to_string(path("old.txt~"))?, "older");
+
+    let mut mmv = Command::cargo_bin("mmv")?;
+    mmv.arg("--undo")
+        .arg(&undo_log)
+        .assert()
+        .success()
+        .stdout(contains("Done").count(2));
+    assert_eq!(std::fs::read_to_string(path("new.txt"))?, "new");
+    assert_eq!(std::fs::read_to_string(path("old.txt"))?, "old");
+    assert!(!path("old.txt.~1~").exists());
+    Ok(temporary_directory.close()?)
+}
+
+#[test]
+fn test_backup_suffix() -> anyhow::Result<()> {
+    let temporary_directory = generate_files(
+        (1..=3).map(|index| PathBuf::from(format!("draft-{index}.md"))),
+        empty(),
+    )?;
+    let path = |name: &str| temporary_directory.path().join(name);
+    std::fs::write(path("final-2.md"), "final")?;
+
+    let mut mmv = Command::cargo_bin("mmv")?;
+    mmv.current_dir(temporary_directory.path())
+        .args([
+            "-f",
+            "--backup",
+            "--backup-suffix=.bak",
+            "draft-*.md",
+            "final-#1.md",
+        ])
+        .assert()
+        .success()
+        .stdout(contains("Done").count(3));
+    assert_eq!(std::fs::read_to_string(path("final-2.md.bak"))?, "final");
+    assert_eq!(std::fs::read_to_string(path("final-2.md"))?, "");
+    assert!(!path("final-1.md.bak").exists());
+
+    let mut mmv = Command::cargo_bin("mmv")?;
+    mmv.current_dir(temporary_directory.path())
+        .args(["--backup", "final-*.md", "draft-#1.md"])
+        .assert()
+        .failure();
+    Ok(temporary_directory.close()?)
+}
+
+#[test]
+fn test_strict() -> anyhow::Result<()> {
+    let temporary_directory =
+        generate_files(["tardis.blue.box"].map(PathBuf::from).into_iter(), empty())?;
+    let mut mmv = Command::cargo_bin("mmv")?;
+    mmv.current_dir(temporary_directory.path())
+        .args(["--strict", "*.*.box", "#1-#3"])
+        .assert()
+        .failure()
+        .stderr(contains(
+            "Destination template refers to wildcard #3, but source pattern has only 2 wildcards",
+        ));
+    assert!(temporary_directory.path().join("tardis.blue.box").exists());
+
+    let mut mmv = Command::cargo_bin("mmv")?;
+    mmv.current_dir(temporary_directory.path())
+        .args(["--strict", "*.*.box", "#2-#1"])
+        .assert()
+        .success();
+    assert!(temporary_directory.path().join("blue-tardis").exists());
+    Ok(temporary_directory.close()?)
+}
+
+#[test]
+fn test_sort() -> anyhow::Result<()> {
+    let temporary_directory = generate_files(
+        [10, 2, 1, 33, 3]
+            .map(|index| PathBuf::from(format!("{index}.png")))
+            .into_iter(),
+        empty(),
+    )?;
+    let moved_files_order = |sort: &str| -> anyhow::Result<Vec<String>> {
+        let output = Command::cargo_bin("mmv")?
+            .current_dir(temporary_directory.path())
+            .args(["--dry-run", "--sort", sort, "*.png", "img_#1.png"])
+            .output()?;
+        Ok(String::from_utf8(output.stdout)?
+            .lines()
+            .map(|line| line.split('"').nth(1).unwrap_or_default().to_string())
+            .collect())
+    };
+    assert_eq!(
+        moved_files_order("natural")?,
+        ["1.png", "2.png", "3.png", "10.png", "33.png"]
+    );
+    assert_eq!(
+        moved_files_order("name")?,
+        ["1.png", "10.png", "2.png", "3.png", "33.png"]
+    );
+    Ok(temporary_directory.close()?)
+}
+
+#[test]
+fn test_jobs() -> anyhow::Result<()> {
+    const FILES_COUNT: usize = 250;
+    let temporary_directory = generate_files(
+        (0..FILES_COUNT).map(|index| PathBuf::from("logs").join(format!("{index:03}.log"))),
+        [PathBuf::from("archive")].into_iter(),
+    )?;
+    let output = Command::cargo_bin("mmv")?
+        .current_dir(temporary_directory.path())
+        .args(["-j", "4", "logs/*.log", "archive/#1.log.old"])
+        .output()?;
+    assert!(output.status.success());
+    let stdout = strip_colors(&String::from_utf8(output.stdout)?);
+    let lines = stdout.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), FILES_COUNT);
+    for (index, line) in lines.iter().enumerate() {
+        assert_eq!(
+            *line,
+            format!("Moving \"logs/{index:03}.log\" -> \"archive/{index:03}.log.old\": Done")
+        );
+    }
+    assert_eq!(
+        read_dir(temporary_directory.path().join("logs"))?.count(),
+        0
+    );
+    assert_eq!(
+        read_dir(temporary_directory.path().join("archive"))?.count(),
+        FILES_COUNT
+    );
+    Ok(temporary_directory.close()?)
+}
+
+#[test]
+fn test_unsorted_moves_are_streamed() -> anyhow::Result<()> {
+    const FILES_COUNT: usize = 2000;
+    let temporary_directory = generate_files(
+        (0..FILES_COUNT)
+            .map(|index| PathBuf::from("logs").join(format!("{index}.log")))
+            .chain([PathBuf::from("logs/keep.log")]),
+        [PathBuf::from("archive")].into_iter(),
+    )?;
+    let undo_log = temporary_directory.path().join("undo.log");
+    let output = Command::cargo_bin("mmv")?
+        .current_dir(temporary_directory.path())
+        .args(["--sort", "none", "--exclude", "keep.log", "--undo-log"])
+        .arg(&undo_log)
+        .args(["logs/*.log", "archive/#1.log.old"])
+        .output()?;
+    assert!(output.status.success());
+    let stdout = strip_colors(&String::from_utf8(output.stdout)?);
+    assert_eq!(stdout.lines().count(), FILES_COUNT);
+    assert!(stdout.lines().all(|line| line.ends_with(": Done")));
+    assert_eq!(
+        String::from_utf8(output.stderr)?,
+        format!("{FILES_COUNT} moved, 0 skipped, 0 failed\n")
+    );
+    assert_eq!(
+        read_dir(temporary_directory.path().join("archive"))?.count(),
+        FILES_COUNT
+    );
+    assert!(temporary_directory.path().join("logs/keep.log").exists());
+
+    Command::cargo_bin("mmv")?
+        .current_dir(temporary_directory.path())
+        .arg("--undo")
+        .arg(&undo_log)
+        .assert()
+        .success();
+    assert_eq!(
+        read_dir(temporary_directory.path().join("logs"))?.count(),
+        FILES_COUNT + 1
+    );
+
+    // Collisions are found before anything is moved
+    std::fs::write(temporary_directory.path().join("logs/7.txt"), "")?;
+    Command::cargo_bin("mmv")?
+        .current_dir(temporary_directory.path())
+        .args(["--sort", "none", "logs/*.*", "archive/#1"])
+        .assert()
+        .failure()
+        .stderr(contains("would both be moved to \"archive/7\""));
+    assert_eq!(
+        read_dir(temporary_directory.path().join("archive"))?.count(),
+        0
+    );
+    Ok(temporary_directory.close()?)
+}
+
+#[test]
+fn test_progress() -> anyhow::Result<()> {
+    let temporary_directory = generate_files(
+        (0..150).map(|index| PathBuf::from(format!("episode-{index}.mkv"))),
+        [PathBuf::from("watched")].into_iter(),
+    )?;
+    std::fs::write(temporary_directory.path().join("watched/7.mkv"), "")?;
+    std::fs::create_dir(temporary_directory.path().join("watched/8.mkv"))?;
+    std::fs::write(temporary_directory.path().join("watched/8.mkv/notes"), "")?;
+    let output = Command::cargo_bin("mmv")?
+        .current_dir(temporary_directory.path())
+        .args(["--progress", "episode-*.mkv", "watched/#1.mkv"])
+        .output()?;
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stdout.is_empty());
+    assert_eq!(
+        String::from_utf8(output.stderr)?,
+        "148 moved, 2 skipped, 0 failed\n"
+    );
+
+    for index in 0..150 {
+        std::fs::write(
+            temporary_directory
+                .path()
+                .join(format!("episode-{index}.mkv")),
+            "",
+        )?;
+    }
+    let output = Command::cargo_bin("mmv")?
+        .current_dir(temporary_directory.path())
+        .args(["--progress", "-f", "episode-*.mkv", "watched/#1.mkv"])
+        .output()?;
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+    let stderr = strip_colors(&String::from_utf8(output.stderr)?);
+    assert_eq!(stderr.lines().count(), 2);
+    assert!(stderr.starts_with("Moving \"episode-8.mkv\" -> \"watched/8.mkv\": Failed"));
+    assert!(stderr.ends_with("149 moved, 0 skipped, 1 failed\n"));
+
+    // No progress bar for exactly `ProgressReporter::THRESHOLD` moves
+    std::fs::create_dir(temporary_directory.path().join("seen"))?;
+    for index in 0..100 {
+        std::fs::write(
+            temporary_directory
+                .path()
+                .join(format!("episode-{index}.mkv")),
+            "",
+        )?;
+    }
+    Command::cargo_bin("mmv")?
+        .current_dir(temporary_directory.path())
+        .args(["--progress", "-f", "episode-*.mkv", "seen/#1.mkv"])
+        .assert()
+        .success()
+        .stdout(contains("Done").count(100));
+    Ok(temporary_directory.close()?)
+}
+
+#[cfg(unix)]
+#[test]
+fn test_symlinks() -> anyhow::Result<()> {
+    let temporary_directory = generate_files(
+        [PathBuf::from("target.txt")].into_iter(),
+        [PathBuf::from("links")].into_iter(),
+    )?;
+    let path = |name: &str| temporary_directory.path().join(name);
+    std::os::unix::fs::symlink("target.txt", path("link-good"))?;
+    std::os::unix::fs::symlink("missing.txt", path("link-dangling"))?;
+
+    Command::cargo_bin("mmv")?
+        .current_dir(temporary_directory.path())
+        .args(["-L", "link-*", "links/#1"])
+        .assert()
+        .success();
+    assert!(path("links/good").symlink_metadata()?.is_symlink());
+    assert!(path("link-dangling").symlink_metadata().is_ok());
+
+    Command::cargo_bin("mmv")?
+        .current_dir(temporary_directory.path())
+        .args(["link-*", "links/#1"])
+        .assert()
+        .success();
+    assert!(path("links/dangling").symlink_metadata()?.is_symlink());
+    assert_eq!(
+        std::fs::read_link(path("links/dangling"))?,
+        PathBuf::from("missing.txt")
+    );
+    Ok(temporary_directory.close()?)
+}
+
+#[test]
+fn test_summary() -> anyhow::Result<()> {
+    let temporary_directory = generate_files(
+        ["a.txt", "b.txt", "c.txt", "moved/b.md"]
+            .map(PathBuf::from)
+            .into_iter(),
+        [PathBuf::from("moved")].into_iter(),
+    )?;
+    Command::cargo_bin("mmv")?
+        .current_dir(temporary_directory.path())
+        .args(["*.txt", "moved/#1.md"])
+        .assert()
+        .success()
+        .stderr("2 moved, 1 skipped, 0 failed\n");
+
+    Command::cargo_bin("mmv")?
+        .current_dir(temporary_directory.path())
+        .args(["--dry-run", "moved/*.md", "#1.txt"])
+        .assert()
+        .success()
+        .stderr("2 planned, 0 moved, 1 skipped, 0 failed\n");
+    Ok(temporary_directory.close()?)
+}
+
+#[test]
+fn test_greedy() -> anyhow::Result<()> {
+    let temporary_directory = generate_files([PathBuf::from("a.rs.rs.rs")].into_iter(), empty())?;
+    Command::cargo_bin("mmv")?
+        .current_dir(temporary_directory.path())
+        .args(["--greedy", "*.rs*.rs", "#1 #2.txt"])
+        .assert()
+        .success();
+    assert!(temporary_directory.path().join("a.rs .txt").exists());
+    Command::cargo_bin("mmv")?
+        .current_dir(temporary_directory.path())
+        .args(["* *.txt", "#1.rs#2.rs"])
+        .assert()
+        .success();
+    Command::cargo_bin("mmv")?
+        .current_dir(temporary_directory.path())
+        .args(["*.rs*.rs", "#1 #2.txt"])
+        .assert()
+        .success();
+    assert!(temporary_directory.path().join("a .rs.txt").exists());
+    Ok(temporary_directory.close()?)
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_non_utf8_filenames_warning() -> anyhow::Result<()> {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+    let temporary_directory =
+        generate_files([PathBuf::from("good.txt")].into_iter(), std::iter::empty())?;
+    std::fs::write(
+        temporary_directory
+            .path()
+            .join(OsStr::from_bytes(b"bad-\xff.txt")),
+        "",
+    )?;
+    Command::cargo_bin("mmv")?
+        .current_dir(temporary_directory.path())
+        .args(["*.txt", "#1.md"])
+        .assert()
+        .success()
+        .stderr(contains("filename is not valid UTF-8"))
+        .stderr(contains("Entries skipped because of non-UTF-8 names: 1"));
+    assert!(temporary_directory.path().join("good.md").exists());
+    Ok(temporary_directory.close()?)
+}
+
+#[test]
+fn test_from_stdin() -> anyhow::Result<()> {
+    let temporary_directory = generate_files(
+        ["a.txt", "b.txt", "c.txt", "notes/d.txt", "e.md"]
+            .map(PathBuf::from)
+            .into_iter(),
+        [PathBuf::from("done")].into_iter(),
+    )?;
+    let path = |name: &str| temporary_directory.path().join(name);
+    assert_cmd::Command::cargo_bin("mmv")?
+        .current_dir(temporary_directory.path())
+        .args(["--from-stdin", "*.txt", "done/#1.text"])
+        .write_stdin("a.txt\nnotes/d.txt\ne.md\n\n")
+        .assert()
+        .success();
+    assert!(path("done/a.text").exists());
+    assert!(path("done/d.text").exists());
+    assert!(path("b.txt").exists());
+    assert!(path("c.txt").exists());
+    assert!(path("e.md").exists());
+
+    assert_cmd::Command::cargo_bin("mmv")?
+        .current_dir(temporary_directory.path())
+        .args(["--from-stdin", "-0", "*.txt", "done/#1.text"])
+        .write_stdin("c.txt\0e.md\0")
+        .assert()
+        .success();
+    assert!(path("done/c.text").exists());
+    assert!(path("b.txt").exists());
+    Ok(temporary_directory.close()?)
+}
+
+#[test]
+fn test_exclude() -> anyhow::Result<()> {
+    let temporary_directory = generate_files(
+        [
+            "server.log",
+            "debug.log",
+            "access.log",
+            "trace-1.log",
+            "trace-2.log",
+        ]
+        .map(PathBuf::from)
+        .into_iter(),
+        [PathBuf::from("old")].into_iter(),
+    )?;
+    Command::cargo_bin("mmv")?
+        .current_dir(temporary_directory.path())
+        .args(["*.log", "old/#1.log", "--exclude", "debug.log"])
+        .assert()
+        .success();
+    let moved = read_dir(temporary_directory.path().join("old"))?
+        .map(|entry| Ok(entry?.file_name().into_string().unwrap()))
+        .collect::<std::io::Result<std::collections::BTreeSet<_>>>()?;
+    assert_eq!(
+        moved,
+        ["access.log", "server.log", "trace-1.log", "trace-2.log"]
+            .map(String::from)
+            .into()
+    );
+    assert!(temporary_directory.path().join("debug.log").exists());
+
+    std::fs::write(temporary_directory.path().join("info.log"), "")?;
+    std::fs::write(temporary_directory.path().join("trace-3.log"), "")?;
+    Command::cargo_bin("mmv")?
+        .current_dir(temporary_directory.path())
+        .args(["*.log", "old/#1.log", "--exclude", "debug*"])
+        .args(["--exclude", "trace-*"])
+        .assert()
+        .success();
+    assert!(temporary_directory.path().join("old/info.log").exists());
+    assert!(temporary_directory.path().join("trace-3.log").exists());
+    assert!(temporary_directory.path().join("debug.log").exists());
+    Ok(temporary_directory.close()?)
+}
+
+#[test]
+fn test_identity_is_unchanged() -> anyhow::Result<()> {
+    let temporary_directory = generate_files(
+        ["a.txt", "b.txt", "c.txt"].map(PathBuf::from).into_iter(),
+        empty(),
+    )?;
+    for args in [["*.txt", "#1.txt"], ["*", "./#0"]] {
+        let output = Command::cargo_bin("mmv")?
+            .current_dir(temporary_directory.path())
+            .args(args)
+            .arg("--force")
+            .output()?;
+        assert_eq!(output.status.code(), Some(0));
+        assert_eq!(
+            String::from_utf8(output.stderr)?,
+            "0 moved, 0 skipped, 0 failed, 3 unchanged\n"
+        );
+        let stdout = strip_colors(&String::from_utf8(output.stdout)?);
+        assert_eq!(stdout.lines().count(), 3);
+        assert!(stdout
+            .lines()
+            .all(|line| line.ends_with("Unchanged: source and destination are the same")));
+    }
+    let mut files = read_dir(temporary_directory.path())?
+        .map(|entry| Ok(entry?.file_name()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    files.sort();
+    assert_eq!(files, ["a.txt", "b.txt", "c.txt"]);
+    Ok(temporary_directory.close()?)
+}
+
+#[test]
+fn test_also() -> anyhow::Result<()> {
+    let temporary_directory = generate_files(
+        ["photo-1.jpg", "photo-2.jpg", "video-1.mp4", "notes.txt"]
+            .map(PathBuf::from)
+            .into_iter(),
+        [PathBuf::from("media")].into_iter(),
+    )?;
+    let path = |name: &str| temporary_directory.path().join(name);
+    Command::cargo_bin("mmv")?
+        .current_dir(temporary_directory.path())
+        .args([
+            "photo-*.jpg",
+            "media/#1.jpg",
+            "--also",
+            "video-*",
+            "media/#1",
+        ])
+        .args(["--also", "notes.txt", "media/1.mp4"])
+        .assert()
+        .failure()
+        .stderr(contains(
+            "Files \"video-1.mp4\" and \"notes.txt\" would both be moved to \"media/1.mp4\"",
+        ));
+    for name in ["photo-1.jpg", "photo-2.jpg", "video-1.mp4", "notes.txt"] {
+        assert!(path(name).exists());
+    }
+    assert_eq!(read_dir(path("media"))?.count(), 0);
+
+    Command::cargo_bin("mmv")?
+        .current_dir(temporary_directory.path())
+        .args([
+            "photo-*.jpg",
+            "media/#1.jpg",
+            "--also",
+            "video-*",
+            "media/#1",
+        ])
+        .assert()
+        .success();
+    for name in ["media/1.jpg", "media/2.jpg", "media/1.mp4", "notes.txt"] {
+        assert!(path(name).exists());
+    }
+    Ok(temporary_directory.close()?)
+}
+
+#[test]
+fn test_recursive_directory_capture() -> anyhow::Result<()> {
+    let temporary_directory = generate_files(
+        [
+            "src/main.rs",
+            "src/x/lib.rs",
+            "src/y/util.rs",
+            "src/y/notes.md",
+        ]
+        .map(PathBuf::from)
+        .into_iter(),
+        [PathBuf::from("flat")].into_iter(),
+    )?;
+    Command::cargo_bin("mmv")?
+        .current_dir(temporary_directory.path())
+        .args(["src/**/*.rs", "flat/[#2]#1.rs"])
+        .assert()
+        .success();
+    for name in [
+        "flat/[]main.rs",
+        "flat/[x]lib.rs",
+        "flat/[y]util.rs",
+        "src/y/notes.md",
+    ] {
+        assert!(temporary_directory.path().join(name).exists(), "{name}");
+    }
+    Ok(temporary_directory.close()?)
+}
+
+#[test]
+fn test_verbose() -> anyhow::Result<()> {
+    let temporary_directory = generate_files(
+        ["report.txt", "report.md"].map(PathBuf::from).into_iter(),
+        [PathBuf::from("drafts.txt")].into_iter(),
+    )?;
+    let output = Command::cargo_bin("mmv")?
+        .current_dir(temporary_directory.path())
+        .args(["--verbose", "*.txt", "#1.text"])
+        .output()?;
+    assert!(output.status.success());
+    let stderr = strip_colors(&String::from_utf8(output.stderr)?);
+    assert!(stderr.contains("\"report.txt\": matched"));
+    assert!(stderr.contains("\"report.md\": doesn't match the pattern"));
+    assert!(stderr.contains("\"drafts.txt\": is a directory"));
+
+    let output = Command::cargo_bin("mmv")?
+        .current_dir(temporary_directory.path())
+        .args(["-n", "--verbose", "--exclude", "*.md", "report.*", "#1.txt"])
+        .output()?;
+    assert!(output.status.success());
+    let stderr = strip_colors(&String::from_utf8(output.stderr)?);
+    assert!(stderr.contains("\"report.text\": matched"));
+    assert!(stderr.contains("\"report.md\": skipped, matches an --exclude pattern"));
+    assert!(!stderr.contains("\"report.md\": matched"));
+
+    let output = Command::cargo_bin("mmv")?
+        .current_dir(temporary_directory.path())
+        .args(["*.md", "#1.markdown"])
+        .output()?;
+    assert_eq!(
+        String::from_utf8(output.stderr)?,
+        "1 moved, 0 skipped, 0 failed\n"
+    );
+    Ok(temporary_directory.close()?)
+}
+
+#[test]
+fn test_move_into_directory() -> anyhow::Result<()> {
+    let temporary_directory = generate_files(
+        ["Harry", "Ron", "Hermy"].map(PathBuf::from).into_iter(),
+        [PathBuf::from("moved")].into_iter(),
+    )?;
+    Command::cargo_bin("mmv")?
+        .current_dir(temporary_directory.path())
+        .args(["H*", "moved/"])
+        .assert()
+        .success()
+        .stdout(contains("Done").count(2));
+    let moved = temporary_directory.path().join("moved");
+    assert!(moved.join("Harry").exists());
+    assert!(moved.join("Hermy").exists());
+    assert!(temporary_directory.path().join("Ron").exists());
+    Ok(temporary_directory.close()?)
+}
+
+#[test]
+fn test_error_on_skip() -> anyhow::Result<()> {
+    let temporary_directory = generate_files(
+        ["a.txt", "b.txt", "b.md"].map(PathBuf::from).into_iter(),
+        empty(),
+    )?;
+    Command::cargo_bin("mmv")?
+        .current_dir(temporary_directory.path())
+        .args(["--error-on-skip", "*.txt", "#1.md"])
+        .assert()
+        .code(2)
+        .stderr("1 moved, 1 skipped, 0 failed\n");
+    assert!(temporary_directory.path().join("a.md").exists());
+
+    Command::cargo_bin("mmv")?
+        .current_dir(temporary_directory.path())
+        .args(["*.txt", "#1.md"])
+        .assert()
+        .code(0);
+    Command::cargo_bin("mmv")?
+        .current_dir(temporary_directory.path())
+        .args(["--error-on-skip", "--force", "*.txt", "#1.md"])
+        .assert()
+        .code(0);
+    Ok(temporary_directory.close()?)
+}
+
+#[test]
+fn test_quiet() -> anyhow::Result<()> {
+    let temporary_directory = generate_files(
+        ["gallifrey.txt", "skaro.txt", "telos.txt"]
+            .map(PathBuf::from)
+            .into_iter(),
+        [PathBuf::from("planets"), PathBuf::from("planets/telos.md")].into_iter(),
+    )?;
+    let output = Command::cargo_bin("mmv")?
+        .current_dir(temporary_directory.path())
+        .args(["-q", "*a*.txt", "planets/#1a#2.md"])
+        .output()?;
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stdout.is_empty());
+    assert!(output.stderr.is_empty());
+    assert!(temporary_directory
+        .path()
+        .join("planets/gallifrey.md")
+        .exists());
+    assert!(temporary_directory.path().join("planets/skaro.md").exists());
+    assert!(!temporary_directory.path().join("skaro.txt").exists());
+
+    let output = Command::cargo_bin("mmv")?
+        .current_dir(temporary_directory.path())
+        .args(["--quiet", "-f", "*.txt", "planets/#1.md"])
+        .output()?;
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+    assert!(strip_colors(&String::from_utf8(output.stderr)?)
+        .starts_with("Moving \"telos.txt\" -> \"planets/telos.md\": Failed"));
+
+    std::fs::write(temporary_directory.path().join("skaro.txt"), "")?;
+    let output = Command::cargo_bin("mmv")?
+        .current_dir(temporary_directory.path())
+        .args(["-q", "--format", "json", "skaro.txt", "planets/skaro.txt"])
+        .output()?;
+    assert_eq!(output.status.code(), Some(0));
+    assert!(String::from_utf8(output.stdout)?.contains("\"status\": \"done\""));
+    Ok(temporary_directory.close()?)
+}
+
+#[test]
+fn test_hidden() -> anyhow::Result<()> {
+    let temporary_directory = generate_files(
+        [".config", "notes"].map(PathBuf::from).into_iter(),
+        [PathBuf::from("moved")].into_iter(),
+    )?;
+    let output = Command::cargo_bin("mmv")?
+        .current_dir(temporary_directory.path())
+        .args(["*", "moved/#1"])
+        .output()?;
+    assert_eq!(output.status.code(), Some(0));
+    assert!(temporary_directory.path().join("moved/notes").exists());
+    assert!(temporary_directory.path().join(".config").exists());
+
+    let output = Command::cargo_bin("mmv")?
+        .current_dir(temporary_directory.path())
+        .args(["--hidden", "*", "moved/#1"])
+        .output()?;
+    assert_eq!(output.status.code(), Some(0));
+    assert!(temporary_directory.path().join("moved/.config").exists());
+
+    std::fs::write(temporary_directory.path().join(".bashrc"), "")?;
+    let output = Command::cargo_bin("mmv")?
+        .current_dir(temporary_directory.path())
+        .args([".*", "moved/dot-#1"])
+        .output()?;
+    assert_eq!(output.status.code(), Some(0));
+    assert!(temporary_directory.path().join("moved/dot-bashrc").exists());
+    Ok(temporary_directory.close()?)
+}
+
+#[test]
+fn test_atomic() -> anyhow::Result<()> {
+    let temporary_directory = generate_files(
+        [
+            "amy.txt",
+            "clara.txt",
+            "donna.txt",
+            "companions/donna.md/notes",
+        ]
+        .map(PathBuf::from)
+        .into_iter(),
+        [].into_iter(),
+    )?;
+    let output = Command::cargo_bin("mmv")?
+        .current_dir(temporary_directory.path())
+        .args(["--atomic", "-f", "*.txt", "companions/#1.md"])
+        .output()?;
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8(output.stderr)?.ends_with("All the moved files are moved back\n"));
+    for file in ["amy.txt", "clara.txt", "donna.txt"] {
+        assert!(temporary_directory.path().join(file).exists());
+    }
+    assert!(!temporary_directory
+        .path()
+        .join("companions/amy.md")
+        .exists());
+    assert!(!temporary_directory
+        .path()
+        .join("companions/clara.md")
+        .exists());
+    Ok(temporary_directory.close()?)
+}
+
+#[test]
+fn test_atomic_report() -> anyhow::Result<()> {
+    let temporary_directory = generate_files(
+        [
+            "amy.txt",
+            "bill.txt",
+            "clara.txt",
+            "companions/bill.md/notes",
+        ]
+        .map(PathBuf::from)
+        .into_iter(),
+        [].into_iter(),
+    )?;
+    let output = Command::cargo_bin("mmv")?
+        .current_dir(temporary_directory.path())
+        .args([
+            "--atomic",
+            "-f",
+            "--format=json",
+            "*.txt",
+            "companions/#1.md",
+        ])
+        .output()?;
+    assert_eq!(output.status.code(), Some(1));
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    let statuses = json
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|record| record["status"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(statuses, ["rolled_back", "failed", "cancelled"]);
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(
+        stderr.contains("0 moved, 0 skipped, 1 failed, 1 cancelled, 1 rolled back\n"),
+        "{stderr}"
+    );
+    for file in ["amy.txt", "bill.txt", "clara.txt"] {
+        assert!(temporary_directory.path().join(file).exists());
+    }
+    Ok(temporary_directory.close()?)
+}
+
+#[test]
+fn test_max_depth() -> anyhow::Result<()> {
+    let temporary_directory = generate_files(
+        ["src/main.rs", "src/x/lib.rs", "src/x/y/util.rs"]
+            .map(PathBuf::from)
+            .into_iter(),
+        [PathBuf::from("flat")].into_iter(),
+    )?;
+    Command::cargo_bin("mmv")?
+        .current_dir(temporary_directory.path())
+        .args(["--max-depth", "1", "src/**/*.rs", "flat/#1.rs"])
+        .assert()
+        .success();
+    for name in ["flat/main.rs", "flat/lib.rs", "src/x/y/util.rs"] {
+        assert!(temporary_directory.path().join(name).exists(), "{name}");
+    }
+    Ok(temporary_directory.close()?)
+}
+
+#[test]
+fn test_copy() -> anyhow::Result<()> {
+    let temporary_directory = generate_files(
+        ["rose.txt", "martha.txt"].map(PathBuf::from).into_iter(),
+        [PathBuf::from("copies")].into_iter(),
+    )?;
+    std::fs::write(temporary_directory.path().join("rose.txt"), "bad wolf")?;
+    let output = Command::cargo_bin("mmv")?
+        .current_dir(temporary_directory.path())
+        .args(["--copy", "-p", "*.txt", "copies/#1.md"])
+        .output()?;
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stderr)?,
+        "2 copied, 0 skipped, 0 failed\n"
+    );
+    for name in [
+        "rose.txt",
+        "martha.txt",
+        "copies/rose.md",
+        "copies/martha.md",
+    ] {
+        assert!(temporary_directory.path().join(name).exists(), "{name}");
+    }
+    assert_eq!(
+        std::fs::read_to_string(temporary_directory.path().join("copies/rose.md"))?,
+        "bad wolf"
+    );
+    Ok(temporary_directory.close()?)
+}
+
+#[test]
+fn test_copy_read_only() -> anyhow::Result<()> {
+    use std::{
+        fs::{File, FileTimes},
+        time::{Duration, SystemTime},
+    };
+
+    let temporary_directory = generate_files(
+        [PathBuf::from("donna.txt")].into_iter(),
+        [PathBuf::from("copies")].into_iter(),
+    )?;
+    let source = temporary_directory.path().join("donna.txt");
+    // 2001-01-01
+    let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(978_307_200);
+    let file = File::options().write(true).open(&source)?;
+    file.set_times(FileTimes::new().set_modified(modified))?;
+    let mut permissions = file.metadata()?.permissions();
+    permissions.set_readonly(true);
+    file.set_permissions(permissions)?;
+    drop(file);
+
+    let output = Command::cargo_bin("mmv")?
+        .current_dir(temporary_directory.path())
+        .args(["--copy", "-p", "*.txt", "copies/#1.txt"])
+        .output()?;
+    assert!(output.status.success());
+    assert!(!String::from_utf8(output.stderr)?.contains("Warning"));
+    let copied = temporary_directory
+        .path()
+        .join("copies/donna.txt")
+        .metadata()?;
+    assert!(copied.permissions().readonly());
+    assert_eq!(copied.modified()?, modified);
+    Ok(temporary_directory.close()?)
+}
+
+#[test]
+fn test_match_path() -> anyhow::Result<()> {
+    let temporary_directory = generate_files(
+        ["notes/abc/x.txt", "notes/abd/y.txt", "notes/b/w.txt"]
+            .map(PathBuf::from)
+            .into_iter(),
+        [PathBuf::from("flat")].into_iter(),
+    )?;
+    Command::cargo_bin("mmv")?
+        .current_dir(temporary_directory.path())
+        .args(["notes/a*/*.txt", "flat/#1-#2.txt"])
+        .assert()
+        .failure();
+    Command::cargo_bin("mmv")?
+        .current_dir(temporary_directory.path())
+        .args(["--match-path", "notes/a*/*.txt", "flat/#1-#2.txt"])
+        .assert()
+        .success();
+    for name in ["flat/bc-x.txt", "flat/bd-y.txt", "notes/b/w.txt"] {
+        assert!(temporary_directory.path().join(name).exists(), "{name}");
+    }
+    Ok(temporary_directory.close()?)
+}
+
+#[test]
+fn test_environment_variables() -> anyhow::Result<()> {
+    let temporary_directory = generate_files(
+        ["amelia.txt", "rory.txt"].map(PathBuf::from).into_iter(),
+        [PathBuf::from("leadworth")].into_iter(),
+    )?;
+    Command::cargo_bin("mmv")?
+        .current_dir(temporary_directory.path())
+        .env("MMV_TEST_DIR", "leadworth")
+        .env("MMV_TEST_SUFFIX", "pond")
+        .args(["*.txt", "$MMV_TEST_DIR/#1-${MMV_TEST_SUFFIX}.txt"])
+        .assert()
+        .success();
+    for name in ["leadworth/amelia-pond.txt", "leadworth/rory-pond.txt"] {
+        assert!(temporary_directory.path().join(name).exists(), "{name}");
+    }
+
+    let output = Command::cargo_bin("mmv")?
+        .current_dir(temporary_directory.path())
+        .env_remove("MMV_TEST_MISSING")
+        .args(["leadworth/*.txt", "$MMV_TEST_MISSING/#1.txt"])
+        .output()?;
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8(output.stderr)?
+        .contains("Environment variable $MMV_TEST_MISSING is not set, use $$ for a literal '$'"));
+
+    Command::cargo_bin("mmv")?
+        .current_dir(temporary_directory.path())
+        .env_remove("USD")
+        .args(["leadworth/amelia-*.txt", "leadworth/c$USD#1.txt"])
+        .assert()
+        .failure();
+    Command::cargo_bin("mmv")?
+        .current_dir(temporary_directory.path())
+        .env_remove("USD")
+        .args(["leadworth/amelia-*.txt", "leadworth/c$$USD#1.txt"])
+        .assert()
+        .success();
+    assert!(temporary_directory
+        .path()
+        .join("leadworth/c$USDpond.txt")
+        .exists());
+    Ok(temporary_directory.close()?)
+}
+
+#[test]
+fn test_stats() -> anyhow::Result<()> {
+    let temporary_directory = generate_files(
+        ["bill.txt", "nardole.txt"].map(PathBuf::from).into_iter(),
+        [PathBuf::from("university")].into_iter(),
+    )?;
+    std::fs::write(temporary_directory.path().join("bill.txt"), "potts")?;
+    std::fs::write(temporary_directory.path().join("nardole.txt"), "123456")?;
+    std::fs::write(
+        temporary_directory.path().join("university/nardole.txt"),
+        "",
+    )?;
+    let output = Command::cargo_bin("mmv")?
+        .current_dir(temporary_directory.path())
+        .args(["--stats", "*.txt", "university/#1.txt"])
+        .output()?;
+    assert_eq!(output.status.code(), Some(0));
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(stderr.contains("\nmoved 5 B in "), "{stderr}");
+    Ok(temporary_directory.close()?)
+}
+
+#[test]
+fn test_on_conflict() -> anyhow::Result<()> {
+    for (policy, expected_code, expected_foo, expected_moved) in [
+        ("skip", 0, "foo", None),
+        ("overwrite", 0, "clara", Some("foo")),
+        ("rename", 0, "foo", Some("foo-2")),
+        ("fail", 1, "foo", None),
+    ] {
+        let temporary_directory = generate_files(
+            ["clara", "foo", "foo-1", "rory"]
+                .map(PathBuf::from)
+                .into_iter(),
+            empty(),
+        )?;
+        let path = |name: &str| temporary_directory.path().join(name);
+        for name in ["clara", "foo", "foo-1"] {
+            std::fs::write(path(name), name)?;
+        }
+        let output = Command::cargo_bin("mmv")?
+            .current_dir(temporary_directory.path())
+            .args([
+                "--on-conflict",
+                policy,
+                "cl*a",
+                "foo",
+                "--also",
+                "ror*",
+                "amy",
+            ])
+            .output()?;
+        assert_eq!(output.status.code(), Some(expected_code), "{policy}");
+        assert_eq!(
+            std::fs::read_to_string(path("foo"))?,
+            expected_foo,
+            "{policy}"
+        );
+        assert_eq!(std::fs::read_to_string(path("foo-1"))?, "foo-1", "{policy}");
+        if let Some(moved) = expected_moved {
+            assert_eq!(std::fs::read_to_string(path(moved))?, "clara", "{policy}");
+            assert!(!path("clara").exists(), "{policy}");
+        } else {
+            assert!(path("clara").exists(), "{policy}");
+        }
+        // Moves without conflicts are performed unless the policy is `fail`
+        assert_eq!(path("amy").exists(), policy != "fail", "{policy}");
+        temporary_directory.close()?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_force_is_overwrite() -> anyhow::Result<()> {
+    let temporary_directory =
+        generate_files(["clara", "foo"].map(PathBuf::from).into_iter(), empty())?;
+    let mut mmv = Command::cargo_bin("mmv")?;
+    mmv.current_dir(temporary_directory.path())
+        .args(["-f", "--backup", "cl*a", "foo"])
+        .assert()
+        .success();
+    assert!(!temporary_directory.path().join("clara").exists());
+    assert!(temporary_directory.path().join("foo~").exists());
+
+    let mut mmv = Command::cargo_bin("mmv")?;
+    mmv.current_dir(temporary_directory.path())
+        .args(["-f", "--on-conflict=skip", "f*", "clara"])
+        .assert()
+        .failure();
+    Ok(temporary_directory.close()?)
+}